@@ -1,4 +1,4 @@
-use int_code_computer::Intcode;
+use int_code_computer::{Intcode, Symbolic};
 use std::fs;
 
 fn main() {
@@ -9,26 +9,32 @@ fn main() {
 
 fn part1(program: String) -> i64 {
     let mut comp = Intcode::intcode_instance(program);
-    comp.restore();
-    comp.run();
+    comp.patch(include_str!("../alarm.patch")).unwrap();
+    comp.run().unwrap();
     comp.read(0) // 3101878
 }
 
 fn part2(program: String) -> i64 {
-    let mut result = 0;
-    'outer: for noun in 0..100 {
-        for verb in 0..100 {
-            let mut comp = Intcode::intcode_instance(program.clone());
-            comp.write(noun, 1);
-            comp.write(verb, 2);
-            comp.run();
-            if comp.read(0) == 19690720 {
-                result = noun * 100 + verb; // 8444
-                break 'outer;
-            }
-        }
-    }
-    result
+    const TARGET: i64 = 19690720;
+    let mut comp = Intcode::intcode_instance(program);
+    let mut sym = Symbolic::new(&comp);
+    sym.set_symbol(1, "noun");
+    sym.set_symbol(2, "verb");
+    sym.run().unwrap();
+    let expr = sym.read(0);
+    let (noun, verb) = (0..100)
+        .find_map(|noun| {
+            let verb = expr.solve("verb", TARGET, &[("noun", noun)])?;
+            (0..100).contains(&verb).then_some((noun, verb))
+        })
+        .expect("no noun and verb give the target");
+    // The expression only holds along the path the symbolic run took, so
+    // check the answer with a concrete run.
+    comp.write(noun, 1).unwrap();
+    comp.write(verb, 2).unwrap();
+    comp.run().unwrap();
+    assert_eq!(comp.read(0), TARGET);
+    noun * 100 + verb // 8444
 }
 
 #[cfg(test)]
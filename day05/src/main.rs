@@ -1,16 +1,36 @@
-use int_code_computer::Intcode;
-use std::fs;
+use int_code_computer::{Coverage, Intcode, RunState};
+use std::{env, fs};
 
 fn main() {
     let contents = fs::read_to_string("input.txt").expect("File not found");
     println!("{}", part1(contents.clone())); // 5182797
-    println!("{}", part2(contents)); // 12077198
+    println!("{}", part2(contents.clone())); // 12077198
+    if env::args().any(|a| a == "--coverage") {
+        print!("{}", coverage(contents));
+    }
+}
+
+// How much of the diagnostic program the two system IDs exercise between them.
+fn coverage(program: String) -> String {
+    let mut coverage = Coverage::new();
+    for id in [1, 5] {
+        let mut comp = Intcode::intcode_instance(program.clone());
+        comp.enable_profiling();
+        comp.set_input(id);
+        comp.run().unwrap();
+        coverage.merge(&comp.coverage().unwrap());
+    }
+    let mem = Intcode::intcode_instance(program).memory();
+    let mut report = coverage.report(&mem);
+    report.push('\n');
+    report.push_str(&coverage.annotate(&mem));
+    report
 }
 
 fn part1(program: String) -> i64 {
     let mut comp = Intcode::intcode_instance(program);
     comp.set_input(1);
-    if comp.run() {
+    if comp.run().unwrap() == RunState::Halted {
         let mut v = comp.get_outputs();
         return v.pop().unwrap();
     }
@@ -20,7 +40,7 @@ fn part1(program: String) -> i64 {
 fn part2(program: String) -> i64 {
     let mut comp = Intcode::intcode_instance(program);
     comp.set_input(5);
-    if comp.run() {
+    if comp.run().unwrap() == RunState::Halted {
         return comp.get_output();
     }
     -1
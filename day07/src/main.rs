@@ -1,5 +1,4 @@
-use int_code_computer::Intcode;
-use itertools::Itertools;
+use int_code_computer::{phase_search, Intcode};
 use std::fs;
 
 fn main() {
@@ -9,50 +8,19 @@ fn main() {
 }
 
 fn part1(program: String) -> i64 {
-    let mut max = i64::MIN;
-    for perm in (0..=4).permutations(5) {
-        let mut output = 0;
-        for p in perm {
-            let mut amp = Intcode::intcode_instance(program.clone());
-            amp.set_input(p);
-            amp.run();
-            amp.set_input(output);
-            amp.run();
-            output = amp.get_output();
-        }
-        max = max.max(output);
-    }
-    max
+    let image = Intcode::intcode_instance(program).image();
+    phase_search(&image, &[0, 1, 2, 3, 4], false)
+        .unwrap()
+        .unwrap()
+        .0
 }
 
 fn part2(program: String) -> i64 {
-    let mut max = i64::MIN;
-    for perm in (5..=9).permutations(5) {
-        let mut amps: Vec<Intcode> = Vec::new();
-        for p in perm {
-            let mut amp = Intcode::intcode_instance(program.clone());
-            amp.set_input(p);
-            amp.run();
-            amps.push(amp);
-        }
-
-        let mut outputs = [0; 5];
-        'outer: loop {
-            for i in 0..amps.len() {
-                let amp = amps.get_mut(i).unwrap();
-                if !amp.is_halted() {
-                    amp.set_input(if i == 0 { outputs[4] } else { outputs[i - 1] });
-                    amp.run();
-                    outputs[i] = amp.get_output();
-                }
-                if i == 4 && amp.is_halted() {
-                    break 'outer;
-                }
-            }
-        }
-        max = max.max(outputs[4]);
-    }
-    max
+    let image = Intcode::intcode_instance(program).image();
+    phase_search(&image, &[5, 6, 7, 8, 9], true)
+        .unwrap()
+        .unwrap()
+        .0
 }
 
 #[cfg(test)]
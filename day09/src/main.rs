@@ -10,14 +10,14 @@ fn main() {
 fn part1(program: String) -> i64 {
     let mut comp = Intcode::intcode_instance(program);
     comp.set_input(1);
-    comp.run();
+    comp.run().unwrap();
     comp.get_output()
 }
 
 fn part2(program: String) -> i64 {
     let mut comp = Intcode::intcode_instance(program);
     comp.set_input(2);
-    comp.run();
+    comp.run().unwrap();
     comp.get_output()
 }
 
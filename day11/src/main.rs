@@ -1,4 +1,5 @@
 use int_code_computer::Intcode;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fs;
 
@@ -96,14 +97,8 @@ impl Robot {
         for y in (self.y_min..=self.y_max).rev() {
             for x in self.x_min..=self.x_max {
                 let c = match self.hull.get(&(x, y)) {
-                    Some(b) => {
-                        if *b {
-                            '*'
-                        } else {
-                            ' '
-                        }
-                    }
-                    None => ' ',
+                    Some(true) => '*',
+                    _ => ' ',
                 };
                 print!("{}", c);
             }
@@ -119,32 +114,37 @@ fn main() {
 }
 
 fn part1(program: String) -> i32 {
-    let mut comp = Intcode::intcode_instance(program);
     let mut robot = Robot::new();
-    while !comp.is_halted() {
-        comp.set_input(robot.get_color() as i64);
-        comp.run();
-        robot.paint(comp.get_output() as i32);
-        robot.turn(comp.get_output() as i32);
-        robot.go();
-    }
+    drive(program, &mut robot);
     robot.hull.len() as i32
 }
 
 fn part2(program: String) {
-    let mut comp = Intcode::intcode_instance(program);
     let mut robot = Robot::new();
     robot.paint(1);
-    while !comp.is_halted() {
-        comp.set_input(robot.get_color() as i64);
-        comp.run();
-        robot.paint(comp.get_output() as i32);
-        robot.turn(comp.get_output() as i32);
-        robot.go();
-    }
+    drive(program, &mut robot);
     robot.print();
 }
 
+// The program reads the camera and drives the motors: it outputs a color to
+// paint, then a direction to turn before moving on.
+fn drive(program: String, robot: &mut Robot) {
+    let robot = RefCell::new(robot);
+    let mut color = None;
+    let camera = || Some(robot.borrow().get_color() as i64);
+    let motors = |v| match color.take() {
+        None => color = Some(v),
+        Some(c) => {
+            let mut robot = robot.borrow_mut();
+            robot.paint(c as i32);
+            robot.turn(v as i32);
+            robot.go();
+        }
+    };
+    let mut comp = Intcode::intcode_instance(program).with_io(camera, motors);
+    comp.run().unwrap();
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
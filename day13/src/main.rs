@@ -1,8 +1,11 @@
-use int_code_computer::Intcode;
+use int_code_computer::{Intcode, RunState};
 use std::collections::HashMap;
 use std::fs;
 use std::iter;
 
+// Far more than a game takes; a program still running by then is stuck.
+const STEP_LIMIT: u64 = 10_000_000;
+
 fn main() {
     let contents = fs::read_to_string("input.txt").expect("File not found");
     println!("{}", part1(contents.clone())); // 236
@@ -12,7 +15,7 @@ fn main() {
 fn part1(program: String) -> i32 {
     let mut comp = Intcode::intcode_instance(program);
     let mut screen: HashMap<(i64, i64), i64> = HashMap::new();
-    comp.run();
+    comp.run().unwrap();
     let outputs = comp.get_outputs();
     let mut output = outputs.iter().peekable();
     while output.peek().is_some() {
@@ -28,11 +31,14 @@ fn part1(program: String) -> i32 {
 fn part2(program: String) -> i64 {
     let mut comp = Intcode::intcode_instance(program);
     //let mut screen: HashMap<(i64, i64), i64> = HashMap::new();
-    comp.write(2, 0);
-    wall_hack(&mut comp);
+    comp.patch(include_str!("../quarters.patch")).unwrap();
+    comp.patch(include_str!("../wall.patch")).unwrap();
+    comp.set_step_limit(Some(STEP_LIMIT));
     let mut zeroes = iter::repeat(0_i64);
     while !comp.is_halted() {
-        comp.run();
+        if let RunState::BudgetExhausted(steps) = comp.run().unwrap() {
+            panic!("arcade still running after {} steps", steps);
+        }
         //let outputs = comp.get_outputs();
         //let mut output = outputs.iter().peekable();
         //while output.peek().is_some() {
@@ -55,13 +61,6 @@ pub fn draw_screen(screen: &HashMap<(i64, i64), i64>) {
         println!();
     }
 }
-fn wall_hack(comp: &mut Intcode) {
-    // Moved the wall above the paddle to avoid infinite loop
-    let base = 1360_i64 - (2 * 36);
-    for x in 0..34 {
-        comp.write(1, base + x);
-    }
-}
 
 #[cfg(test)]
 mod tests {
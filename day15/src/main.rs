@@ -1,7 +1,7 @@
 #![allow(dead_code)]
 use console::Term;
-use int_code_computer::Intcode;
-use std::collections::HashMap;
+use int_code_computer::{Intcode, RunState};
+use std::collections::{HashMap, HashSet};
 use std::fs;
 //use std::{thread, time};
 
@@ -67,8 +67,8 @@ impl MyMap {
 }
 
 fn main() {
-    let contents = fs::read_to_string("map.txt").expect("File not found");
-    let result = part1_part2(contents);
+    let contents = fs::read_to_string("input.txt").expect("File not found");
+    let result = explore(contents);
     println!("{}", result.0); // 218
     println!("{}", result.1); // 544
                               //robot_sim(contents);
@@ -78,7 +78,7 @@ fn robot_sim(program: String) {
     let mut comp = Intcode::intcode_instance(program);
     let mut map = MyMap::default();
     let mut pos = (0, 0);
-    comp.run();
+    comp.run().unwrap();
     while !comp.is_halted() {
         let direction = get_input();
         let poi = match direction {
@@ -92,7 +92,7 @@ fn robot_sim(program: String) {
             continue;
         }
         comp.set_input(direction as i64);
-        comp.run();
+        comp.run().unwrap();
         match comp.get_output() {
             0 => {
                 map.insert(poi, '#');
@@ -111,6 +111,49 @@ fn robot_sim(program: String) {
     }
 }
 
+// Maps the whole maze with a BFS that forks the droid at every open cell,
+// then floods it with oxygen.
+fn explore(program: String) -> (i32, i32) {
+    let mut map = MyMap::default();
+    let mut seen = HashSet::from([(0, 0)]);
+    let mut frontier = vec![((0, 0), Intcode::intcode_instance(program))];
+    let mut oxygen = (0, 0);
+    map.insert((0, 0), 'S');
+    while !frontier.is_empty() {
+        let mut next = Vec::new();
+        for (pos, droid) in &frontier {
+            for direction in 1..=4 {
+                let poi = match direction {
+                    1 => (pos.0, pos.1 + 1),
+                    2 => (pos.0, pos.1 - 1),
+                    3 => (pos.0 - 1, pos.1),
+                    _ => (pos.0 + 1, pos.1),
+                };
+                if !seen.insert(poi) {
+                    continue;
+                }
+                let mut fork = droid.fork();
+                fork.push_input(direction);
+                match fork.run_until_output().unwrap() {
+                    RunState::Output(0) => map.insert(poi, '#'),
+                    RunState::Output(1) => {
+                        map.insert(poi, '.');
+                        next.push((poi, fork));
+                    }
+                    RunState::Output(2) => {
+                        map.insert(poi, '0');
+                        oxygen = poi;
+                        next.push((poi, fork));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        frontier = next;
+    }
+    fill(map, oxygen)
+}
+
 fn part1_part2(contents: String) -> (i32, i32) {
     let mut map = MyMap::default();
     let mut start = (0, 0);
@@ -122,7 +165,11 @@ fn part1_part2(contents: String) -> (i32, i32) {
             map.insert((x as i32, y as i32), c);
         }
     }
+    fill(map, start)
+}
 
+// Returns the steps from start to 'S' and the steps to fill the whole maze.
+fn fill(mut map: MyMap, start: (i32, i32)) -> (i32, i32) {
     let mut q = Vec::<(i32, i32)>::new();
     let mut q2 = Vec::<(i32, i32)>::new();
     q.push(start);
@@ -188,4 +235,10 @@ mod tests {
         assert_eq!(result.0, 218);
         assert_eq!(result.1, 544);
     }
+
+    #[test]
+    fn test_explore() {
+        let contents = fs::read_to_string("input.txt").expect("File not found");
+        assert_eq!(explore(contents), (218, 544));
+    }
 }
@@ -1,4 +1,4 @@
-use int_code_computer::Intcode;
+use int_code_computer::{AsciiIntcode, Intcode};
 use std::fs;
 
 fn main() {
@@ -9,9 +9,7 @@ fn main() {
 
 fn part1(program: String) -> usize {
     let mut comp = Intcode::intcode_instance(program);
-    comp.run();
-    let output = comp.get_outputs();
-    let o: String = output.iter().map(|i| (*i as u8) as char).collect();
+    let o = comp.read_text().unwrap();
     let v: Vec<Vec<char>> = o.lines().map(|s| s.chars().collect()).collect();
 
     let mut alignment = 0;
@@ -29,34 +27,14 @@ fn part1(program: String) -> usize {
 
 fn part2(program: String) -> i64 {
     let mut comp = Intcode::intcode_instance(program);
-    comp.write(2, 0);
-    comp.run();
-    for b in b"B,C,C,A,A,B,B,C,C,A\n" {
-        // Main program
-        comp.set_input(*b as i64);
-        comp.run();
-    }
-    for b in b"R,12,R,4,L,6,L,8,L,8\n" {
-        // Program A
-        comp.set_input(*b as i64);
-        comp.run();
-    }
-    for b in b"L,12,R,4,R,4\n" {
-        // Program B
-        comp.set_input(*b as i64);
-        comp.run();
-    }
-    for b in b"R,12,R,4,L,12\n" {
-        // Program C
-        comp.set_input(*b as i64);
-        comp.run();
-    }
-    for b in b"n\n" {
-        // No video feed
-        comp.set_input(*b as i64);
-        comp.run();
-    }
-    comp.get_last_output()
+    comp.patch(include_str!("../wake.patch")).unwrap();
+    comp.send_line("B,C,C,A,A,B,B,C,C,A").unwrap(); // Main program
+    comp.send_line("R,12,R,4,L,6,L,8,L,8").unwrap(); // Program A
+    comp.send_line("L,12,R,4,R,4").unwrap(); // Program B
+    comp.send_line("R,12,R,4,L,12").unwrap(); // Program C
+    comp.send_line("n").unwrap(); // No video feed
+    comp.read_text().unwrap();
+    comp.get_output()
 }
 
 fn is_intersection(map: &[Vec<char>], row: usize, col: usize) -> bool {
@@ -1,4 +1,5 @@
 use int_code_computer::Intcode;
+use std::env;
 use std::fs;
 
 fn main() {
@@ -6,6 +7,22 @@ fn main() {
     println!("{}", part1(contents)); // 121
     contents = fs::read_to_string("input.txt").expect("File not found");
     println!("{}", part2(contents)); // 15090773
+    if env::args().any(|a| a == "--profile") {
+        contents = fs::read_to_string("input.txt").expect("File not found");
+        print!("{}", profile(contents));
+    }
+}
+
+// Where the drone program spends its time while scanning the 50x50 area.
+fn profile(program: String) -> String {
+    let mut comp = Intcode::intcode_instance(program);
+    comp.enable_profiling();
+    for y in 0..50 {
+        for x in 0..50 {
+            is_pulled(&mut comp, x, y);
+        }
+    }
+    comp.profile_report(15).unwrap()
 }
 
 fn part1(program: String) -> i64 {
@@ -13,11 +30,11 @@ fn part1(program: String) -> i64 {
     for y in 0..50 {
         for x in 0..50 {
             comp.reset();
-            comp.run();
+            comp.run().unwrap();
             comp.set_input(x);
-            comp.run();
+            comp.run().unwrap();
             comp.set_input(y);
-            comp.run();
+            comp.run().unwrap();
         }
     }
     comp.get_outputs().into_iter().sum()
@@ -43,11 +60,11 @@ fn part2(program: String) -> i64 {
 
 fn is_pulled(comp: &mut Intcode, x: i64, y: i64) -> bool {
     comp.reset();
-    comp.run();
+    comp.run().unwrap();
     comp.set_input(x);
-    comp.run();
+    comp.run().unwrap();
     comp.set_input(y);
-    comp.run();
+    comp.run().unwrap();
     comp.get_last_output() == 1
 }
 
@@ -1,4 +1,4 @@
-use int_code_computer::Intcode;
+use int_code_computer::{AsciiIntcode, Intcode};
 use std::fs;
 
 fn main() {
@@ -8,25 +8,27 @@ fn main() {
 }
 
 fn part1(program: String) -> i64 {
-    let mut comp = Intcode::intcode_instance(program);
-    comp.run();
-    let bytes = b"NOT B J\nNOT C T\nOR T J\nAND D J\nNOT A T\nOR T J\nWALK\n";
-    for byte in bytes.iter() {
-        comp.set_input(*byte as i64);
-        comp.run();
-    }
-    comp.get_last_output()
+    survey(
+        program,
+        "NOT B J\nNOT C T\nOR T J\nAND D J\nNOT A T\nOR T J\nWALK",
+    )
 }
 
 fn part2(program: String) -> i64 {
+    survey(
+        program,
+        "NOT B J\nNOT C T\nOR T J\nAND D J\nAND H J\nNOT A T\nOR T J\nRUN",
+    )
+}
+
+fn survey(program: String, script: &str) -> i64 {
     let mut comp = Intcode::intcode_instance(program);
-    comp.run();
-    let bytes = b"NOT B J\nNOT C T\nOR T J\nAND D J\nAND H J\nNOT A T\nOR T J\nRUN\n";
-    for byte in bytes.iter() {
-        comp.set_input(*byte as i64);
-        comp.run();
+    comp.read_until_prompt("Input instructions:\n").unwrap();
+    for line in script.lines() {
+        comp.send_line(line).unwrap();
     }
-    comp.get_last_output()
+    comp.read_text().unwrap();
+    comp.get_output()
 }
 
 #[cfg(test)]
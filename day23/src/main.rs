@@ -1,5 +1,4 @@
-use int_code_computer::Intcode;
-use std::collections::VecDeque;
+use int_code_computer::{FirstPacket, Intcode, Nat, Network, Packet};
 use std::fs;
 
 fn main() {
@@ -9,104 +8,49 @@ fn main() {
 }
 
 fn part1(program: String) -> i64 {
-    let mut computers: Vec<Intcode> = Vec::new();
-    let mut messages: Vec<VecDeque<i64>> = Vec::new();
-    for i in 0..50 {
-        let mut comp = Intcode::intcode_instance(program.clone());
-        comp.set_input(i as i64);
-        comp.run();
-        computers.push(comp);
-        messages.push(VecDeque::new());
-    }
+    let mut net = Network::new(Intcode::intcode_instance(program).image(), 50);
+    let mut nat = FirstPacket::default();
+    net.run(&mut nat).unwrap();
+    nat.packet.unwrap().values[1]
+}
 
-    loop {
-        for i in 0..50 {
-            let comp = &mut computers[i];
-            if comp.awaiting_input() {
-                let q = &mut messages[i];
-                if q.is_empty() {
-                    comp.set_input(-1);
-                } else {
-                    comp.set_input(q.pop_front().unwrap());
-                }
-            }
-            comp.step();
-            if comp.packet_ready() {
-                let v = comp.get_outputs();
-                let addr = v[0];
-                if addr == 255 {
-                    return v[2];
-                }
-                let q = &mut messages[addr as usize];
-                q.push_back(v[1]);
-                q.push_back(v[2]);
-            }
-        }
-    }
+// Keeps the last packet sent to it and sends it to 0 whenever the network
+// goes idle, until it would send the same y twice in a row.
+#[derive(Default)]
+struct Restarter {
+    last: Option<Packet>,
+    sent_y: Option<i64>,
+    repeated_y: Option<i64>,
 }
 
-fn part2(program: String) -> i64 {
-    let mut computers: Vec<Intcode> = Vec::new();
-    let mut messages: Vec<VecDeque<i64>> = Vec::new();
-    let mut nat_x = 0;
-    let mut nat_y = 0;
-    let mut sent = -1;
-    for i in 0..50 {
-        let mut comp = Intcode::intcode_instance(program.clone());
-        comp.set_input(i as i64);
-        comp.run();
-        computers.push(comp);
-        messages.push(VecDeque::new());
+impl Nat for Restarter {
+    fn receive(&mut self, packet: &Packet) -> bool {
+        self.last = Some(packet.clone());
+        true
     }
 
-    let mut idle = [0; 50];
-    loop {
-        for i in 0..50 {
-            let comp = &mut computers[i];
-            if comp.awaiting_input() {
-                let q = &mut messages[i];
-                if q.is_empty() {
-                    comp.set_input(-1);
-                    idle[i] += 1;
-                } else {
-                    comp.set_input(q.pop_front().unwrap());
-                    idle[i] = 0;
-                }
-            }
-            comp.step();
-            if comp.packet_ready() {
-                let v = comp.get_outputs();
-                let addr = v[0];
-                if addr == 255 {
-                    nat_x = v[1];
-                    nat_y = v[2];
-                } else {
-                    let q = &mut messages[addr as usize];
-                    q.push_back(v[1]);
-                    q.push_back(v[2]);
-                }
-            }
-        }
-        let mut all_idle = true;
-        for id in idle {
-            if id < 2 {
-                all_idle = false;
-                break;
-            }
-        }
-        if all_idle {
-            if sent == nat_y {
-                return nat_y;
-            }
-            let q = &mut messages[0];
-            q.push_back(nat_x);
-            q.push_back(nat_y);
-            sent = nat_y;
-            idle[0] = 0;
+    fn wake(&mut self) -> Option<Packet> {
+        let values = self.last.as_ref()?.values.clone();
+        if self.sent_y == Some(values[1]) {
+            self.repeated_y = self.sent_y;
+            return None;
         }
+        self.sent_y = Some(values[1]);
+        Some(Packet {
+            from: 255,
+            to: 0,
+            values,
+        })
     }
 }
 
+fn part2(program: String) -> i64 {
+    let mut net = Network::new(Intcode::intcode_instance(program).image(), 50);
+    let mut nat = Restarter::default();
+    net.run(&mut nat).unwrap();
+    nat.repeated_y.unwrap()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,4 +1,4 @@
-use int_code_computer::Intcode;
+use int_code_computer::{AsciiError, AsciiIntcode, Intcode};
 use std::fs;
 use std::io::stdin;
 fn main() {
@@ -8,33 +8,41 @@ fn main() {
 
 fn _play(program: String) {
     let mut comp = Intcode::intcode_instance(program);
-    comp.run();
+    let mut o = comp.read_until_prompt("Command?\n").unwrap();
     loop {
-        let output = comp.get_outputs();
-        let o: String = output.iter().map(|i| (*i as u8) as char).collect();
         println!("{o}");
         let mut line = String::new();
         _ = stdin().read_line(&mut line);
-        for b in line.trim().as_bytes() {
-            comp.set_input(*b as i64);
-            comp.run();
+        // "save <name>" and "load <name>" checkpoint the game, e.g. before
+        // picking up an item that might be lethal.
+        if let Some(name) = line.trim().strip_prefix("save ") {
+            comp.save_named(name);
+            o = format!("Saved {name}");
+        } else if let Some(name) = line.trim().strip_prefix("load ") {
+            if comp.restore_named(name) {
+                o = format!("Loaded {name}");
+            } else {
+                o = format!("No save named {name}");
+            }
+        } else {
+            comp.send_line(line.trim()).unwrap();
+            o = match comp.read_until_prompt("Command?\n") {
+                Ok(text) => text,
+                Err(AsciiError::MissingPrompt(text)) => format!("{text}\nGame over, load a save"),
+                Err(e) => panic!("{e}"),
+            };
         }
-        comp.set_input(10_i64);
-        comp.run();
     }
 }
 
 fn part1(program: String) -> u32 {
     let mut comp = Intcode::intcode_instance(program);
-    comp.run();
+    let mut o = comp.read_text().unwrap();
     let command = "east\ntake ornament\nsouth\ntake festive hat\nnorth\nwest\nnorth\nnorth\ntake space heater\neast\ntake semiconductor\nwest\nsouth\nsouth\nwest\nnorth\nnorth\nwest\n";
-    for b in command.as_bytes() {
-        let _ = comp.get_outputs();
-        comp.set_input(*b as i64);
-        comp.run();
+    for line in command.lines() {
+        comp.send_line(line).unwrap();
+        o = comp.read_text().unwrap();
     }
-    let output = comp.get_outputs();
-    let o: String = output.iter().map(|i| (*i as u8) as char).collect();
     o.split(' ').nth(49).unwrap().parse::<u32>().unwrap()
 }
 
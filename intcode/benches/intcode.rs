@@ -0,0 +1,41 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use int_code_computer::Intcode;
+use std::fs;
+
+fn boost(c: &mut Criterion) {
+    let program = fs::read_to_string("../day09/input.txt").expect("File not found");
+    c.bench_function("day09 boost", |b| {
+        b.iter(|| {
+            let mut comp = Intcode::intcode_instance(program.clone());
+            comp.set_input(black_box(2));
+            comp.run().unwrap();
+            comp.get_output()
+        })
+    });
+}
+
+fn beam_scan(c: &mut Criterion) {
+    let program = fs::read_to_string("../day19/input.txt").expect("File not found");
+    c.bench_function("day19 beam scan", |b| {
+        b.iter(|| {
+            let mut comp = Intcode::intcode_instance(program.clone());
+            let mut pulled = 0;
+            for y in 0..50 {
+                for x in 0..50 {
+                    comp.reset();
+                    comp.extend_input([x, y]);
+                    comp.run().unwrap();
+                    pulled += comp.get_output();
+                }
+            }
+            pulled
+        })
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = boost, beam_scan
+}
+criterion_main!(benches);
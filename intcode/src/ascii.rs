@@ -0,0 +1,136 @@
+use crate::{Intcode, IntcodeError, RunState};
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsciiError {
+    Intcode(IntcodeError),
+    NotAscii(i64),
+    MissingPrompt(String),
+}
+
+impl fmt::Display for AsciiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsciiError::Intcode(e) => write!(f, "{}", e),
+            AsciiError::NotAscii(v) => write!(f, "{} is not an ASCII value", v),
+            AsciiError::MissingPrompt(text) => write!(f, "prompt not found in {:?}", text),
+        }
+    }
+}
+
+impl Error for AsciiError {}
+
+impl From<IntcodeError> for AsciiError {
+    fn from(e: IntcodeError) -> Self {
+        AsciiError::Intcode(e)
+    }
+}
+
+fn is_ascii(v: i64) -> bool {
+    (0..128).contains(&v)
+}
+
+fn to_char(v: i64) -> Result<char, AsciiError> {
+    if is_ascii(v) {
+        Ok(v as u8 as char)
+    } else {
+        Err(AsciiError::NotAscii(v))
+    }
+}
+
+pub trait AsciiIntcode {
+    fn send_line(&mut self, line: &str) -> Result<(), AsciiError>;
+    fn read_text(&mut self) -> Result<String, AsciiError>;
+    fn read_until_prompt(&mut self, prompt: &str) -> Result<String, AsciiError>;
+}
+
+impl AsciiIntcode for Intcode {
+    // Queues the line followed by a newline; the machine is not run.
+    fn send_line(&mut self, line: &str) -> Result<(), AsciiError> {
+        if let Some(c) = line.chars().find(|c| !c.is_ascii()) {
+            return Err(AsciiError::NotAscii(c as i64));
+        }
+        self.extend_input(line.bytes().map(|b| b as i64));
+        self.push_input(10);
+        Ok(())
+    }
+
+    // Runs until halt or input is needed and returns everything printed.
+    // A trailing non-ASCII value (usually the answer) is left in the output
+    // queue for get_output. On an error the output queue is left as it was.
+    fn read_text(&mut self) -> Result<String, AsciiError> {
+        self.run()?;
+        let mut n = self.output.len();
+        if matches!(self.output.back(), Some(v) if !is_ascii(*v)) {
+            n -= 1;
+        }
+        let text = (self.output.iter().take(n))
+            .map(|v| to_char(*v))
+            .collect::<Result<_, _>>()?;
+        self.output.drain(..n);
+        Ok(text)
+    }
+
+    fn read_until_prompt(&mut self, prompt: &str) -> Result<String, AsciiError> {
+        let mut text: String = (self.output.iter())
+            .map(|v| to_char(*v))
+            .collect::<Result<_, _>>()?;
+        self.output.clear();
+        while !text.ends_with(prompt) {
+            match self.run_until_output()? {
+                RunState::Output(v) => text.push(to_char(v)?),
+                _ => return Err(AsciiError::MissingPrompt(text)),
+            }
+        }
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AsciiError, AsciiIntcode};
+    use crate::Intcode as Computer;
+
+    #[test]
+    fn read_text_with_trailing_value() {
+        let s = "104, 72, 104, 105, 104, 10, 104, 1000, 99".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        assert_eq!(comp.read_text().unwrap(), "Hi\n");
+        assert_eq!(comp.get_output(), 1000);
+    }
+
+    #[test]
+    fn read_text_rejects_non_ascii() {
+        let s = "104, 500, 104, 10, 99".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        assert_eq!(comp.read_text(), Err(AsciiError::NotAscii(500)));
+        assert_eq!(comp.get_outputs(), vec![500, 10]);
+    }
+
+    #[test]
+    fn send_line_echo() {
+        let s = "3, 100, 4, 100, 1008, 100, 10, 101, 1006, 101, 0, 99".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        comp.send_line("WALK").unwrap();
+        assert_eq!(comp.read_text().unwrap(), "WALK\n");
+    }
+
+    #[test]
+    fn read_until_prompt() {
+        let s = "104, 62, 104, 32, 104, 33, 3, 0, 99".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        assert_eq!(comp.read_until_prompt("> ").unwrap(), "> ");
+        assert_eq!(comp.read_text().unwrap(), "!");
+    }
+
+    #[test]
+    fn missing_prompt() {
+        let s = "104, 63, 99".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        assert_eq!(
+            comp.read_until_prompt("Command?"),
+            Err(AsciiError::MissingPrompt("?".to_string()))
+        );
+    }
+}
@@ -0,0 +1,271 @@
+use crate::instruction::{Instruction, Mode, Opcode, Param};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmErrorKind {
+    UnknownMnemonic(String),
+    BadOperand(String),
+    WrongArity { expected: usize, found: usize },
+    ImmediateWrite,
+    DuplicateLabel(String),
+    UndefinedLabel(String),
+    AddressMismatch { expected: usize, found: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            AsmErrorKind::UnknownMnemonic(m) => write!(f, "unknown mnemonic {}", m),
+            AsmErrorKind::BadOperand(o) => write!(f, "bad operand {}", o),
+            AsmErrorKind::WrongArity { expected, found } => {
+                write!(f, "expected {} operands, found {}", expected, found)
+            }
+            AsmErrorKind::ImmediateWrite => write!(f, "destination in immediate mode"),
+            AsmErrorKind::DuplicateLabel(l) => write!(f, "duplicate label {}", l),
+            AsmErrorKind::UndefinedLabel(l) => write!(f, "undefined label {}", l),
+            AsmErrorKind::AddressMismatch { expected, found } => {
+                write!(f, "address {} does not match position {}", expected, found)
+            }
+        }
+    }
+}
+
+impl Error for AsmError {}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Num(i64),
+    Label(String, i64),
+}
+
+#[derive(Debug, Clone)]
+enum Item {
+    Instr(Opcode, Vec<(Mode, Expr)>),
+    Data(Vec<Expr>),
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_expr(s: &str) -> Option<Expr> {
+    let s = s.trim();
+    if let Ok(n) = s.parse::<i64>() {
+        return Some(Expr::Num(n));
+    }
+    let (label, offset) = match s.find(['+', '-']) {
+        Some(i) => {
+            let off = s[i..].replace(' ', "").parse::<i64>().ok()?;
+            (s[..i].trim(), off)
+        }
+        None => (s, 0),
+    };
+    is_ident(label).then(|| Expr::Label(label.to_string(), offset))
+}
+
+fn parse_operand(s: &str) -> Option<(Mode, Expr)> {
+    let s = s.trim();
+    if let Some(rest) = s.strip_prefix('#') {
+        return Some((Mode::Immediate, parse_expr(rest)?));
+    }
+    if let Some(rest) = s.strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+        return Some((Mode::Position, parse_expr(rest)?));
+    }
+    if let Some(rest) = s.strip_prefix("rb") {
+        let rest = rest.replace(' ', "");
+        let off = if rest.is_empty() {
+            0
+        } else {
+            rest.parse().ok()?
+        };
+        return Some((Mode::Relative, Expr::Num(off)));
+    }
+    None
+}
+
+fn parse_item(text: &str) -> Result<Option<Item>, AsmErrorKind> {
+    if text.is_empty() {
+        return Ok(None);
+    }
+    if let Some(rest) = text.strip_prefix(".data") {
+        let values = rest
+            .split(',')
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| parse_expr(v).ok_or_else(|| AsmErrorKind::BadOperand(v.trim().to_string())))
+            .collect::<Result<Vec<_>, _>>()?;
+        return Ok(Some(Item::Data(values)));
+    }
+    let (mnemonic, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let opcode = Opcode::from_mnemonic(mnemonic)
+        .ok_or_else(|| AsmErrorKind::UnknownMnemonic(mnemonic.to_string()))?;
+    let operands = rest
+        .split(',')
+        .filter(|o| !o.trim().is_empty())
+        .map(|o| parse_operand(o).ok_or_else(|| AsmErrorKind::BadOperand(o.trim().to_string())))
+        .collect::<Result<Vec<_>, _>>()?;
+    if operands.len() != opcode.arity() {
+        return Err(AsmErrorKind::WrongArity {
+            expected: opcode.arity(),
+            found: operands.len(),
+        });
+    }
+    if opcode.writes() && operands[opcode.arity() - 1].0 == Mode::Immediate {
+        return Err(AsmErrorKind::ImmediateWrite);
+    }
+    Ok(Some(Item::Instr(opcode, operands)))
+}
+
+fn resolve(e: &Expr, labels: &HashMap<String, usize>) -> Result<i64, AsmErrorKind> {
+    match e {
+        Expr::Num(n) => Ok(*n),
+        Expr::Label(l, off) => labels
+            .get(l)
+            .map(|a| *a as i64 + off)
+            .ok_or_else(|| AsmErrorKind::UndefinedLabel(l.clone())),
+    }
+}
+
+// Assembles source text into the words of a program.
+//
+// Each line holds optional labels (`name:`), then an instruction such as
+// `ADD [4], #3, rb-1` or a `.data 1, 2, label` directive. Text after `;` is a
+// comment. A numeric label like `12:` asserts the current address, so the
+// disassembler's listing assembles back to the original program.
+pub fn assemble_words(src: &str) -> Result<Vec<i64>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut items = Vec::new();
+    let mut addr = 0;
+    for (i, line) in src.lines().enumerate() {
+        let err = |kind| AsmError { line: i + 1, kind };
+        let mut text = line.split(';').next().unwrap().trim();
+        while let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            if let Ok(expected) = label.parse::<usize>() {
+                if expected != addr {
+                    return Err(err(AsmErrorKind::AddressMismatch {
+                        expected,
+                        found: addr,
+                    }));
+                }
+            } else if !is_ident(label) {
+                return Err(err(AsmErrorKind::BadOperand(label.to_string())));
+            } else if labels.insert(label.to_string(), addr).is_some() {
+                return Err(err(AsmErrorKind::DuplicateLabel(label.to_string())));
+            }
+            text = rest.trim();
+        }
+        if let Some(item) = parse_item(text).map_err(err)? {
+            addr += match &item {
+                Item::Instr(op, _) => op.arity() + 1,
+                Item::Data(v) => v.len(),
+            };
+            items.push((i + 1, item));
+        }
+    }
+
+    let mut words = Vec::with_capacity(addr);
+    for (line, item) in items {
+        let err = |kind| AsmError { line, kind };
+        match item {
+            Item::Instr(opcode, operands) => {
+                let params = operands
+                    .iter()
+                    .map(|(mode, e)| resolve(e, &labels).map(|value| Param { mode: *mode, value }))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(err)?;
+                words.extend(Instruction::new(opcode, &params).encode());
+            }
+            Item::Data(values) => {
+                for e in &values {
+                    words.push(resolve(e, &labels).map_err(err)?);
+                }
+            }
+        }
+    }
+    Ok(words)
+}
+
+// Assembles source text into a comma separated program for intcode_instance.
+pub fn assemble(src: &str) -> Result<String, AsmError> {
+    let words = assemble_words(src)?;
+    Ok(words
+        .iter()
+        .map(|w| w.to_string())
+        .collect::<Vec<_>>()
+        .join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassemble;
+    use crate::Intcode as Computer;
+
+    #[test]
+    fn labels_and_data() {
+        let src = "
+            ; count down from 3, printing each value
+            loop: OUT [n]
+                  ADD [n], #-1, [n]
+                  JT [n], #loop
+                  HLT
+            n:    .data 3
+        ";
+        let program = assemble(src).unwrap();
+        assert_eq!(program, "4,10,1001,10,-1,10,1005,10,0,99,3");
+        let mut comp = Computer::intcode_instance(program);
+        comp.run().unwrap();
+        assert_eq!(comp.get_outputs(), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn relative_operands() {
+        let words = assemble_words("ARB #5\nOUT rb-2\nIN rb\nHLT").unwrap();
+        assert_eq!(words, vec![109, 5, 204, -2, 203, 0, 99]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mem = vec![1, 9, 10, 9, 1105, 1, 8, 7, 99, 5, 6];
+        let listing = disassemble(&mem).to_string();
+        assert_eq!(assemble_words(&listing).unwrap(), mem);
+    }
+
+    #[test]
+    fn errors() {
+        let e = assemble("HLT\nFOO #1").unwrap_err();
+        assert_eq!(e.line, 2);
+        assert_eq!(e.kind, AsmErrorKind::UnknownMnemonic("FOO".to_string()));
+        let e = assemble("JT #1, #nowhere").unwrap_err();
+        assert_eq!(e.kind, AsmErrorKind::UndefinedLabel("nowhere".to_string()));
+        let e = assemble("ADD #1, #2, #3").unwrap_err();
+        assert_eq!(e.kind, AsmErrorKind::ImmediateWrite);
+        let e = assemble("OUT #1, #2").unwrap_err();
+        assert_eq!(
+            e.kind,
+            AsmErrorKind::WrongArity {
+                expected: 1,
+                found: 2
+            }
+        );
+        let e = assemble("HLT\n0: HLT").unwrap_err();
+        assert_eq!(
+            e.kind,
+            AsmErrorKind::AddressMismatch {
+                expected: 0,
+                found: 1
+            }
+        );
+    }
+}
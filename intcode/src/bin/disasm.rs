@@ -0,0 +1,8 @@
+use int_code_computer::Intcode;
+use std::env;
+
+fn main() {
+    let path = env::args().nth(1).expect("Usage: disasm <program>");
+    let comp = Intcode::from_path(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    print!("{}", comp.disassemble());
+}
@@ -0,0 +1,323 @@
+use int_code_computer::{decode, AsciiIntcode, Intcode, Trace};
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::io::{stdin, stdout, Write};
+
+const HELP: &str = "\
+b <addr>         set a breakpoint
+d <addr>         delete a breakpoint
+bl               list breakpoints
+s [n]            step n instructions (default 1)
+c                continue until a breakpoint, halt or input is needed
+l [addr] [n]     list n instructions from addr (default pc, 10)
+m <addr> [n]     show n memory values from addr (default 1)
+w <addr> <val>   write val to addr
+rb [val]         show or set the relative base
+in <v> ...       queue input values
+ascii <text>     queue text followed by a newline
+out              show pending output
+clear            drop pending output
+trace <file|off> write a JSONL trace of executed instructions
+profile [on|off|n] start or stop profiling, or show the top n hot spots
+dot <file>       write the control-flow graph as Graphviz DOT
+decompile <file> write the program as pseudocode
+r                show pc, rb and the next instruction
+q                quit";
+
+struct Debugger {
+    comp: Intcode,
+    breakpoints: BTreeSet<usize>,
+}
+
+fn parse<T: std::str::FromStr>(arg: Option<&str>, what: &str) -> Result<T, String> {
+    let arg = arg.ok_or(format!("missing {what}"))?;
+    arg.parse().map_err(|_| format!("bad {what}: {arg}"))
+}
+
+impl Debugger {
+    fn new(comp: Intcode) -> Self {
+        Self {
+            comp,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    fn location(&self) -> String {
+        let pc = self.comp.pc();
+        let instr = match decode(&self.comp.memory(), pc) {
+            Ok(i) => i.to_string(),
+            Err(_) => format!(".data {}", self.comp.read(pc)),
+        };
+        let state = if self.comp.is_halted() {
+            " (halted)"
+        } else if self.comp.awaiting_input() {
+            " (needs input)"
+        } else {
+            ""
+        };
+        format!("pc {} rb {}: {}{}", pc, self.comp.rb(), instr, state)
+    }
+
+    fn stopped(&self) -> bool {
+        self.comp.is_halted() || self.comp.awaiting_input()
+    }
+
+    fn step(&mut self, n: usize) -> Result<String, String> {
+        for _ in 0..n {
+            self.comp.step().map_err(|e| e.to_string())?;
+            if self.stopped() {
+                break;
+            }
+        }
+        Ok(self.location())
+    }
+
+    fn cont(&mut self) -> Result<String, String> {
+        loop {
+            self.comp.step().map_err(|e| e.to_string())?;
+            if self.stopped() {
+                return Ok(self.location());
+            }
+            if self.breakpoints.contains(&self.comp.pc()) {
+                return Ok(format!("breakpoint\n{}", self.location()));
+            }
+        }
+    }
+
+    fn list(&self, from: usize, n: usize) -> String {
+        let mem = self.comp.memory();
+        let mut lines = Vec::new();
+        let mut addr = from;
+        for _ in 0..n {
+            let marker = if addr == self.comp.pc() { '>' } else { ' ' };
+            match decode(&mem, addr) {
+                Ok(i) => {
+                    lines.push(format!("{}{:>5}: {}", marker, addr, i));
+                    addr += i.size();
+                }
+                Err(_) => {
+                    lines.push(format!(
+                        "{}{:>5}: .data {}",
+                        marker,
+                        addr,
+                        self.comp.read(addr)
+                    ));
+                    addr += 1;
+                }
+            }
+        }
+        lines.join("\n")
+    }
+
+    fn command(&mut self, line: &str) -> Result<String, String> {
+        let mut args = line.split_whitespace();
+        let cmd = args.next().unwrap_or("");
+        match cmd {
+            "" => Ok(String::new()),
+            "help" | "h" => Ok(HELP.to_string()),
+            "b" => {
+                let addr = parse(args.next(), "address")?;
+                self.breakpoints.insert(addr);
+                Ok(format!("breakpoint at {addr}"))
+            }
+            "d" => {
+                let addr = parse(args.next(), "address")?;
+                self.breakpoints.remove(&addr);
+                Ok(format!("deleted breakpoint at {addr}"))
+            }
+            "bl" => Ok(self
+                .breakpoints
+                .iter()
+                .map(|b| b.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")),
+            "s" => {
+                let n = match args.next() {
+                    Some(n) => parse(Some(n), "count")?,
+                    None => 1,
+                };
+                self.step(n)
+            }
+            "c" => self.cont(),
+            "l" => {
+                let from = match args.next() {
+                    Some(a) => parse(Some(a), "address")?,
+                    None => self.comp.pc(),
+                };
+                let n = match args.next() {
+                    Some(n) => parse(Some(n), "count")?,
+                    None => 10,
+                };
+                Ok(self.list(from, n))
+            }
+            "m" => {
+                let addr: usize = parse(args.next(), "address")?;
+                let n: usize = match args.next() {
+                    Some(n) => parse(Some(n), "count")?,
+                    None => 1,
+                };
+                Ok((addr..addr + n)
+                    .map(|a| format!("{:>5}: {}", a, self.comp.read(a)))
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            }
+            "w" => {
+                let addr: i64 = parse(args.next(), "address")?;
+                let val = parse(args.next(), "value")?;
+                self.comp.write(val, addr).map_err(|e| e.to_string())?;
+                Ok(format!("{:>5}: {}", addr, val))
+            }
+            "rb" => {
+                if let Some(v) = args.next() {
+                    self.comp.set_rb(parse(Some(v), "value")?);
+                }
+                Ok(format!("rb {}", self.comp.rb()))
+            }
+            "in" => {
+                for v in args {
+                    self.comp.push_input(parse(Some(v), "value")?);
+                }
+                Ok(format!("input {:?}", self.comp.peek_inputs()))
+            }
+            "ascii" => {
+                let text = line.trim_start()[cmd.len()..].trim();
+                self.comp.send_line(text).map_err(|e| e.to_string())?;
+                Ok(format!("input {:?}", self.comp.peek_inputs()))
+            }
+            "out" => {
+                let out = self.comp.peek_outputs();
+                let text: Option<String> = out
+                    .iter()
+                    .map(|v| u8::try_from(*v).ok().map(char::from))
+                    .map(|c| c.filter(|c| c.is_ascii_graphic() || c.is_ascii_whitespace()))
+                    .collect();
+                match text {
+                    Some(t) if !out.is_empty() => Ok(t),
+                    _ => Ok(format!("{:?}", out)),
+                }
+            }
+            "clear" => {
+                self.comp.get_outputs();
+                Ok(String::new())
+            }
+            "trace" => match args.next() {
+                Some("off") => {
+                    self.comp.clear_trace();
+                    Ok("tracing off".to_string())
+                }
+                Some(path) => {
+                    let trace = Trace::file(path).map_err(|e| e.to_string())?;
+                    self.comp.set_trace(trace);
+                    Ok(format!("tracing to {path}"))
+                }
+                None => Err("missing file".to_string()),
+            },
+            "profile" => match args.next() {
+                Some("on") => {
+                    self.comp.enable_profiling();
+                    Ok("profiling on".to_string())
+                }
+                Some("off") => {
+                    self.comp.take_profile();
+                    Ok("profiling off".to_string())
+                }
+                n => {
+                    let top = match n {
+                        Some(n) => parse(Some(n), "count")?,
+                        None => 10,
+                    };
+                    let report = self.comp.profile_report(top);
+                    let report = report.ok_or("profiling is off, try profile on")?;
+                    Ok(report.trim_end().to_string())
+                }
+            },
+            "dot" => {
+                let path = args.next().ok_or("missing file")?;
+                let cfg = self.comp.control_flow();
+                fs::write(path, cfg.to_dot()).map_err(|e| e.to_string())?;
+                Ok(format!("{} blocks written to {path}", cfg.blocks.len()))
+            }
+            "decompile" => {
+                let path = args.next().ok_or("missing file")?;
+                fs::write(path, self.comp.decompile()).map_err(|e| e.to_string())?;
+                Ok(format!("pseudocode written to {path}"))
+            }
+            "r" => Ok(self.location()),
+            _ => Err(format!("unknown command {cmd}, try help")),
+        }
+    }
+}
+
+fn main() {
+    let path = env::args().nth(1).expect("Usage: icdb <program>");
+    let comp = Intcode::from_path(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    let mut dbg = Debugger::new(comp);
+    println!("{}", dbg.location());
+    loop {
+        print!("(icdb) ");
+        stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        let line = line.trim();
+        if line == "q" {
+            break;
+        }
+        match dbg.command(line) {
+            Ok(s) if s.is_empty() => {}
+            Ok(s) => println!("{s}"),
+            Err(e) => println!("error: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn breakpoints_and_continue() {
+        let s = "3, 100, 4, 100, 1001, 100, 1, 100, 3, 101, 1105, 1, 2".to_string();
+        let mut dbg = Debugger::new(Intcode::intcode_instance(s));
+        assert_eq!(
+            dbg.command("c").unwrap(),
+            "pc 0 rb 0: IN [100] (needs input)"
+        );
+        dbg.command("in 7 0").unwrap();
+        dbg.command("b 8").unwrap();
+        assert_eq!(dbg.command("c").unwrap(), "breakpoint\npc 8 rb 0: IN [101]");
+        assert_eq!(dbg.command("out").unwrap(), "[7]");
+        assert_eq!(dbg.command("m 100").unwrap(), "  100: 8");
+    }
+
+    #[test]
+    fn step_and_edit() {
+        let s = "3, 100, 4, 100, 1001, 100, 1, 100, 3, 101, 1105, 1, 2".to_string();
+        let mut dbg = Debugger::new(Intcode::intcode_instance(s));
+        dbg.command("in 1").unwrap();
+        assert_eq!(
+            dbg.command("s 2").unwrap(),
+            "pc 4 rb 0: ADD [100], #1, [100]"
+        );
+        dbg.command("w 100 41").unwrap();
+        dbg.command("rb 3").unwrap();
+        dbg.command("s").unwrap();
+        assert_eq!(dbg.comp.read(100), 42);
+        assert_eq!(dbg.comp.rb(), 3);
+        assert!(dbg.command("m x").is_err());
+        assert!(dbg.command("frobnicate").is_err());
+    }
+
+    #[test]
+    fn ascii_output() {
+        let mut dbg = Debugger::new(Intcode::intcode_instance(
+            "104, 72, 104, 105, 99".to_string(),
+        ));
+        dbg.command("c").unwrap();
+        assert_eq!(dbg.command("out").unwrap(), "Hi");
+        dbg.command("clear").unwrap();
+        assert_eq!(dbg.command("out").unwrap(), "[]");
+    }
+}
@@ -0,0 +1,307 @@
+use crate::disasm::{
+    always_jumps, constant_result, never_jumps, reachable_code, return_site, walk,
+};
+use crate::instruction::{Instruction, Mode, Opcode, Param};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::Write;
+
+// Where control can go when a block ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    // A jump taken, conditional or not.
+    Jump(usize),
+    // The next block, when a conditional jump is not taken or a block simply
+    // runs into a leader.
+    Fall(usize),
+    // Where a call made by the block's final jump returns to.
+    Return(usize),
+    // A jump whose target could not be worked out.
+    Indirect(Param),
+    Halt,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    pub instrs: Vec<(usize, Instruction)>,
+    pub exits: Vec<Exit>,
+}
+
+impl Block {
+    // The address after the last instruction.
+    pub fn end(&self) -> usize {
+        let (addr, instr) = self.instrs[self.instrs.len() - 1];
+        addr + instr.size()
+    }
+}
+
+// The basic blocks of a program, by start address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cfg {
+    pub blocks: BTreeMap<usize, Block>,
+}
+
+// The values a jump through [p] can take: those stored at p by constant ADDs
+// or MULs, or what p holds if nothing writes it. None if anything else writes
+// p. Writes in relative mode are assumed to stay clear of p, which holds for
+// the stacks compiled programs keep.
+fn resolve(mem: &[i64], code: &BTreeMap<usize, Instruction>, p: i64) -> Option<Vec<i64>> {
+    let mut targets = Vec::new();
+    for instr in code.values() {
+        let dest = match instr.params().last() {
+            Some(d) if instr.opcode.writes() => *d,
+            _ => continue,
+        };
+        if dest.mode == Mode::Position && dest.value == p {
+            targets.push(constant_result(instr)?);
+        }
+    }
+    if targets.is_empty() {
+        targets.push(*mem.get(usize::try_from(p).ok()?)?);
+    }
+    targets.sort_unstable();
+    targets.dedup();
+    Some(targets)
+}
+
+// Where a jump can go: its immediate target, the resolved targets of a
+// position-mode target, or None if it is indirect.
+fn targets(
+    mem: &[i64],
+    code: &BTreeMap<usize, Instruction>,
+    jump: &Instruction,
+) -> Option<Vec<usize>> {
+    if never_jumps(jump) {
+        return Some(Vec::new());
+    }
+    let target = jump.params()[1];
+    let values = match target.mode {
+        Mode::Immediate => vec![target.value],
+        Mode::Position => resolve(mem, code, target.value)?,
+        Mode::Relative => return None,
+    };
+    values
+        .into_iter()
+        .map(|v| usize::try_from(v).ok())
+        .collect()
+}
+
+fn is_jump(instr: &Instruction) -> bool {
+    matches!(instr.opcode, Opcode::Jt | Opcode::Jf)
+}
+
+// Walks the program from address 0 like reachable_code, and also follows the
+// jumps through memory it can resolve.
+pub fn control_flow(mem: &[i64]) -> Cfg {
+    let mut code = reachable_code(mem);
+    // Targets that do not decode stay out of code, so only try each once.
+    let mut tried = HashSet::new();
+    loop {
+        let roots: Vec<_> = (code.values())
+            .filter(|i| is_jump(i))
+            .filter_map(|i| targets(mem, &code, i))
+            .flatten()
+            .filter(|t| !code.contains_key(t) && tried.insert(*t))
+            .collect();
+        if roots.is_empty() {
+            break;
+        }
+        walk(mem, roots, &mut code);
+    }
+
+    let mut leaders: BTreeSet<usize> = BTreeSet::from([0]);
+    let mut prev_end = None;
+    for (&addr, instr) in &code {
+        if prev_end != Some(addr) {
+            leaders.insert(addr);
+        }
+        let next = addr + instr.size();
+        if is_jump(instr) {
+            leaders.extend(targets(mem, &code, instr).unwrap_or_default());
+        }
+        if is_jump(instr) || instr.opcode == Opcode::Hlt {
+            leaders.insert(next);
+        }
+        prev_end = Some(next);
+    }
+
+    let mut blocks = BTreeMap::new();
+    let mut block: Option<Block> = None;
+    for (&addr, instr) in &code {
+        if leaders.contains(&addr) {
+            // A block followed by something that does not decode has no exit.
+            if let Some(mut b) = block.take() {
+                if b.end() == addr {
+                    b.exits.push(Exit::Fall(addr));
+                }
+                blocks.insert(b.start, b);
+            }
+        }
+        let b = block.get_or_insert_with(|| Block {
+            start: addr,
+            instrs: Vec::new(),
+            exits: Vec::new(),
+        });
+        b.instrs.push((addr, *instr));
+        if !(is_jump(instr) || instr.opcode == Opcode::Hlt) {
+            continue;
+        }
+        let mut b = block.take().unwrap();
+        let next = addr + instr.size();
+        if instr.opcode == Opcode::Hlt {
+            b.exits.push(Exit::Halt);
+        } else {
+            match targets(mem, &code, instr) {
+                Some(ts) => b.exits.extend(ts.into_iter().map(Exit::Jump)),
+                None => b.exits.push(Exit::Indirect(instr.params()[1])),
+            }
+            if !always_jumps(instr) {
+                if code.contains_key(&next) {
+                    b.exits.push(Exit::Fall(next));
+                }
+            } else if let [.., (_, call), _] = b.instrs[..] {
+                b.exits
+                    .extend(return_site(mem, &call, addr).map(Exit::Return));
+            }
+        }
+        blocks.insert(b.start, b);
+    }
+    // Code running off the end of memory.
+    if let Some(b) = block {
+        blocks.insert(b.start, b);
+    }
+    Cfg { blocks }
+}
+
+impl Cfg {
+    // Graphviz DOT, one node per block. Taken jumps are solid, fall-throughs
+    // dotted and call returns dashed; indirect jumps lead to a ? node.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "digraph intcode {{").unwrap();
+        writeln!(out, "    node [shape=box, fontname=monospace];").unwrap();
+        let mut indirect = false;
+        for b in self.blocks.values() {
+            let label: String = (b.instrs.iter())
+                .map(|(a, i)| format!("{}: {}\\l", a, i))
+                .collect();
+            writeln!(out, "    b{} [label=\"{}\"];", b.start, label).unwrap();
+            for exit in &b.exits {
+                let edge = match exit {
+                    Exit::Jump(t) => format!("b{}", t),
+                    Exit::Fall(t) => format!("b{} [style=dotted]", t),
+                    Exit::Return(t) => format!("b{} [style=dashed]", t),
+                    Exit::Indirect(p) => {
+                        indirect = true;
+                        format!("indirect [label=\"{}\"]", p)
+                    }
+                    Exit::Halt => continue,
+                };
+                writeln!(out, "    b{} -> {};", b.start, edge).unwrap();
+            }
+        }
+        if indirect {
+            writeln!(out, "    indirect [shape=diamond, label=\"?\"];").unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble_words;
+
+    fn exits(cfg: &Cfg) -> Vec<(usize, Vec<Exit>)> {
+        (cfg.blocks.values())
+            .map(|b| (b.start, b.exits.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn branches_and_calls() {
+        let mem = assemble_words(
+            "
+                IN [x]
+                JT [x], #skip
+                ADD #ret, #0, rb+0
+                JF #0, #fn
+        ret:    OUT #1
+        skip:   HLT
+        fn:     JF #0, rb+0
+        x:      .data 0
+            ",
+        )
+        .unwrap();
+        let cfg = control_flow(&mem);
+        assert_eq!(
+            exits(&cfg),
+            vec![
+                (0, vec![Exit::Jump(14), Exit::Fall(5)]),
+                (5, vec![Exit::Jump(15), Exit::Return(12)]),
+                (12, vec![Exit::Fall(14)]),
+                (14, vec![Exit::Halt]),
+                (
+                    15,
+                    vec![Exit::Indirect(Param {
+                        mode: Mode::Relative,
+                        value: 0
+                    })]
+                ),
+            ]
+        );
+        assert_eq!(cfg.blocks[&5].end(), 12);
+        let dot = cfg.to_dot();
+        assert!(dot.contains("    b0 [label=\"0: IN [18]\\l2: JT [18], #14\\l\"];\n"));
+        assert!(dot.contains("    b0 -> b5 [style=dotted];\n"));
+        assert!(dot.contains("    b5 -> b12 [style=dashed];\n"));
+        assert!(dot.contains("    b15 -> indirect [label=\"rb+0\"];\n"));
+    }
+
+    #[test]
+    fn resolves_jumps_through_memory() {
+        let mem = assemble_words(
+            "
+                ADD #a, #0, [t]
+                IN [x]
+                JT [x], #go
+                ADD #b, #0, [t]
+        go:     JT #1, [t]
+        a:      OUT #1
+                HLT
+        b:      OUT #2
+                HLT
+        t:      .data 0
+        x:      .data 0
+            ",
+        )
+        .unwrap();
+        let cfg = control_flow(&mem);
+        assert_eq!(cfg.blocks[&13].exits, vec![Exit::Jump(16), Exit::Jump(19)]);
+        assert!(cfg.blocks.contains_key(&19));
+    }
+
+    #[test]
+    fn target_that_does_not_decode() {
+        // JT #1, [3]; .data 77
+        let cfg = control_flow(&[105, 1, 3, 77]);
+        assert_eq!(exits(&cfg), vec![(0, vec![Exit::Jump(77)])]);
+    }
+
+    #[test]
+    fn no_fall_across_a_gap() {
+        // JT [20], #8; ADD #1, #1, [20]; .data 42; OUT #5; HLT
+        let mut mem = vec![1005, 20, 8, 1101, 1, 1, 20, 42, 104, 5, 99];
+        mem.resize(21, 0);
+        let cfg = control_flow(&mem);
+        assert_eq!(
+            exits(&cfg),
+            vec![
+                (0, vec![Exit::Jump(8), Exit::Fall(3)]),
+                (3, vec![]),
+                (8, vec![Exit::Halt]),
+            ]
+        );
+    }
+}
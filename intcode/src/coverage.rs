@@ -0,0 +1,225 @@
+use crate::disasm::{listing, reachable_code, walk, Line};
+use crate::instruction::{decode, Instruction};
+use crate::profile::Profile;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::ops::Range;
+
+// Which addresses runs of a program executed as instructions, and which its
+// instructions read or wrote. Built from profiles, so several runs with
+// different inputs merge into one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    pub executed: BTreeSet<usize>,
+    pub accessed: BTreeSet<usize>,
+}
+
+fn hits(counts: &[u64]) -> impl Iterator<Item = usize> + '_ {
+    (counts.iter().enumerate())
+        .filter(|(_, n)| **n > 0)
+        .map(|(a, _)| a)
+}
+
+// Sorted addresses as runs of consecutive ones.
+fn ranges<I: IntoIterator<Item = usize>>(addrs: I) -> Vec<Range<usize>> {
+    let mut out: Vec<Range<usize>> = Vec::new();
+    for a in addrs {
+        match out.last_mut() {
+            Some(r) if r.end == a => r.end += 1,
+            _ => out.push(a..a + 1),
+        }
+    }
+    out
+}
+
+fn show(r: &Range<usize>) -> String {
+    if r.len() == 1 {
+        r.start.to_string()
+    } else {
+        format!("{}-{}", r.start, r.end - 1)
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_profile(profile: &Profile) -> Self {
+        let mut coverage = Self::new();
+        coverage.add(profile);
+        coverage
+    }
+
+    pub fn add(&mut self, profile: &Profile) {
+        self.executed.extend(hits(&profile.executed));
+        self.accessed.extend(hits(&profile.reads));
+        self.accessed.extend(hits(&profile.writes));
+    }
+
+    pub fn merge(&mut self, other: &Coverage) {
+        self.executed.extend(&other.executed);
+        self.accessed.extend(&other.accessed);
+    }
+
+    // Every address inside an executed instruction, decoded from mem.
+    fn code(&self, mem: &[i64]) -> BTreeSet<usize> {
+        (self.executed.iter())
+            .flat_map(|&a| a..a + decode(mem, a).map_or(1, |i| i.size()))
+            .collect()
+    }
+
+    // What the disassembler reaches from address 0 and from every executed
+    // address, so code only reached through jumps the program patched in still
+    // decodes as code.
+    fn reachable(&self, mem: &[i64]) -> BTreeMap<usize, Instruction> {
+        let mut code = reachable_code(mem);
+        walk(mem, self.executed.iter().copied().collect(), &mut code);
+        code
+    }
+
+    // Runs of addresses covered by executed instructions.
+    pub fn covered(&self, mem: &[i64]) -> Vec<Range<usize>> {
+        ranges(self.code(mem))
+    }
+
+    // Runs of instructions the disassembler finds reachable that never ran.
+    pub fn unreached(&self, mem: &[i64]) -> Vec<Range<usize>> {
+        let code = self.code(mem);
+        ranges(
+            self.reachable(mem)
+                .into_iter()
+                .filter(|(a, _)| !self.executed.contains(a))
+                .flat_map(|(a, i)| a..a + i.size())
+                .filter(|a| !code.contains(a)),
+        )
+    }
+
+    // Addresses read or written but never executed.
+    pub fn data_only(&self, mem: &[i64]) -> Vec<usize> {
+        let code = self.code(mem);
+        (self.accessed.iter().copied())
+            .filter(|a| !code.contains(a))
+            .collect()
+    }
+
+    pub fn report(&self, mem: &[i64]) -> String {
+        let reachable = self.reachable(mem);
+        let run = reachable
+            .keys()
+            .filter(|a| self.executed.contains(a))
+            .count();
+        let mut out = String::new();
+        writeln!(
+            out,
+            "{} of {} reachable instructions executed, {} executed in all",
+            run,
+            reachable.len(),
+            self.executed.len()
+        )
+        .unwrap();
+        for (title, runs) in [
+            ("covered", self.covered(mem)),
+            ("unreached", self.unreached(mem)),
+            ("data", ranges(self.data_only(mem))),
+        ] {
+            let runs: Vec<_> = runs.iter().map(show).collect();
+            let runs = if runs.is_empty() {
+                "none".to_string()
+            } else {
+                runs.join(", ")
+            };
+            writeln!(out, "{}: {}", title, runs).unwrap();
+        }
+        out
+    }
+
+    // A listing of mem with a column in front of each line: + for code that
+    // ran, - for code that did not, * for data that was read or written.
+    pub fn annotate(&self, mem: &[i64]) -> String {
+        let mut out = String::new();
+        for line in &listing(mem, &self.reachable(mem)).lines {
+            let marker = match line {
+                Line::Instr { addr, .. } if self.executed.contains(addr) => '+',
+                Line::Instr { .. } => '-',
+                Line::Data { addr, values, .. } => {
+                    let span = *addr..addr + values.len();
+                    if span.clone().any(|a| self.executed.contains(&a)) {
+                        '+'
+                    } else if span.clone().any(|a| self.accessed.contains(&a)) {
+                        '*'
+                    } else {
+                        ' '
+                    }
+                }
+            };
+            writeln!(out, "{} {}", marker, line).unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Intcode as Computer;
+
+    #[test]
+    fn one_run() {
+        // IN [12]; JT [12], #9; OUT #1; HLT; OUT #2; HLT; .data 0
+        let s = "3, 12, 1005, 12, 9, 104, 1, 99, 0, 104, 2, 99, 0".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        let mem = comp.memory();
+        comp.enable_profiling();
+        comp.push_input(0);
+        comp.run().unwrap();
+        let coverage = comp.coverage().unwrap();
+        assert_eq!(coverage.covered(&mem), vec![0..8]);
+        assert_eq!(coverage.unreached(&mem), vec![9..12]);
+        assert_eq!(coverage.data_only(&mem), vec![12]);
+        assert_eq!(
+            coverage.report(&mem),
+            "4 of 6 reachable instructions executed, 4 executed in all\n\
+             covered: 0-7\n\
+             unreached: 9-11\n\
+             data: 12\n"
+        );
+    }
+
+    #[test]
+    fn merged_runs() {
+        // IN [12]; JT [12], #9; OUT #1; HLT; OUT #2; HLT; .data 0
+        let s = "3, 12, 1005, 12, 9, 104, 1, 99, 0, 104, 2, 99, 0".to_string();
+        let mem = Computer::intcode_instance(s.clone()).memory();
+        let mut coverage = Coverage::new();
+        for input in [0, 1] {
+            let mut comp = Computer::intcode_instance(s.clone());
+            comp.enable_profiling();
+            comp.push_input(input);
+            comp.run().unwrap();
+            coverage.merge(&comp.coverage().unwrap());
+        }
+        assert_eq!(coverage.covered(&mem), vec![0..8, 9..12]);
+        assert!(coverage.unreached(&mem).is_empty());
+        let listing = coverage.annotate(&mem);
+        assert!(listing.starts_with("+     0: IN [12]\n"));
+        assert!(listing.contains("\n      8: .data 0 ; unreached\n"));
+        assert!(listing.ends_with("\n*    12: .data 0 ; reached\n"));
+    }
+
+    #[test]
+    fn code_behind_a_patched_jump() {
+        // ADD #1100, #5, [4]; .data 0, 1, 8, 0; OUT #7; HLT
+        let s = "1101, 1100, 5, 4, 0, 1, 8, 0, 104, 7, 99".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        let mem = comp.memory();
+        comp.enable_profiling();
+        comp.run().unwrap();
+        let coverage = comp.coverage().unwrap();
+        assert!(coverage
+            .report(&mem)
+            .starts_with("3 of 3 reachable instructions executed, 4 executed in all\n"));
+        let listing = coverage.annotate(&mem);
+        assert!(listing.contains("\n+     8: OUT #7\n+    10: HLT\n"));
+    }
+}
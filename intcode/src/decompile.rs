@@ -0,0 +1,866 @@
+use crate::cfg::{control_flow, Block, Cfg, Exit};
+use crate::disasm::{always_jumps, never_jumps};
+use crate::instruction::{Mode, Opcode, Param};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+
+// Compiled programs call a function by storing the return address at rb+0 and
+// the arguments from rb+1 on, then jumping to it. The function opens with ARB
+// #n, so its frame holds the return address at rb-n and the arguments above
+// it, and returns with ARB #-n and a jump through rb+0. A result is left in
+// the first argument.
+//
+// Relative addresses are named by their offset from rb on entry to the
+// function: v1.. for the frame, o1.. for the arguments of calls it makes.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Stmt {
+    Line(String),
+    If {
+        cond: String,
+        negated: bool,
+        then: Vec<Stmt>,
+        els: Vec<Stmt>,
+    },
+    Loop(Vec<Stmt>),
+    While {
+        cond: String,
+        negated: bool,
+        body: Vec<Stmt>,
+    },
+    Break,
+    Continue,
+    Return,
+    Halt,
+    Goto(usize),
+    Label(usize),
+}
+
+impl Stmt {
+    fn ends_flow(&self) -> bool {
+        matches!(
+            self,
+            Stmt::Break | Stmt::Continue | Stmt::Return | Stmt::Halt | Stmt::Goto(_)
+        )
+    }
+}
+
+// How a block hands on control.
+enum End {
+    Halt,
+    Return,
+    Indirect(Param),
+    Call(Vec<usize>, usize),
+    Next(usize),
+    // Value tested, whether control jumps when it is non-zero, taken, fall.
+    Branch(Param, bool, usize, usize),
+    Leave(usize),
+    Stop,
+}
+
+struct Function {
+    entry: usize,
+    blocks: BTreeSet<usize>,
+    // rb relative to its value on entry, at the start of each block; None
+    // where it depends on the path taken or on a computed ARB.
+    delta: HashMap<usize, Option<i64>>,
+    frame: i64,
+    params: i64,
+}
+
+fn name(entry: usize) -> String {
+    match entry {
+        0 => "main".to_string(),
+        _ => format!("f{}", entry),
+    }
+}
+
+// The rb delta after each instruction of a block.
+fn deltas(block: &Block, start: Option<i64>) -> Vec<Option<i64>> {
+    let mut d = start;
+    let mut out = Vec::new();
+    for (_, instr) in &block.instrs {
+        out.push(d);
+        if instr.opcode == Opcode::Arb {
+            let p = instr.params()[0];
+            d = d.filter(|_| p.mode == Mode::Immediate).map(|d| d + p.value);
+        }
+    }
+    out.push(d);
+    out
+}
+
+fn is_call(block: &Block) -> bool {
+    block.exits.iter().any(|e| matches!(e, Exit::Return(_)))
+}
+
+fn jumps(block: &Block) -> Vec<usize> {
+    (block.exits.iter())
+        .filter_map(|e| match e {
+            Exit::Jump(t) => Some(*t),
+            _ => None,
+        })
+        .collect()
+}
+
+fn successors(block: &Block, entries: &BTreeSet<usize>) -> Vec<usize> {
+    let mut out = Vec::new();
+    for exit in &block.exits {
+        match exit {
+            Exit::Fall(t) | Exit::Return(t) => out.push(*t),
+            Exit::Jump(t) if !is_call(block) && !entries.contains(t) => out.push(*t),
+            _ => {}
+        }
+    }
+    out
+}
+
+impl Function {
+    fn new(cfg: &Cfg, entries: &BTreeSet<usize>, entry: usize) -> Self {
+        let mut blocks = BTreeSet::new();
+        let mut delta: HashMap<usize, Option<i64>> = HashMap::from([(entry, Some(0))]);
+        let mut work = vec![entry];
+        while let Some(start) = work.pop() {
+            let Some(block) = cfg.blocks.get(&start) else {
+                continue;
+            };
+            blocks.insert(start);
+            let end = *deltas(block, delta[&start]).last().unwrap();
+            for next in successors(block, entries) {
+                match delta.get(&next) {
+                    None => {
+                        delta.insert(next, end);
+                        work.push(next);
+                    }
+                    Some(d) if d.is_some() && *d != end => {
+                        delta.insert(next, None);
+                        work.push(next);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut frame = 0;
+        let mut first_read: BTreeMap<i64, bool> = BTreeMap::new();
+        for start in &blocks {
+            let block = &cfg.blocks[start];
+            for ((_, instr), d) in block.instrs.iter().zip(deltas(block, delta[start])) {
+                let Some(d) = d else { continue };
+                frame = frame.max(d);
+                for (i, p) in instr.params().iter().enumerate() {
+                    if p.mode == Mode::Relative {
+                        let read = !(instr.opcode.writes() && i == instr.params().len() - 1);
+                        first_read.entry(d + p.value).or_insert(read);
+                    }
+                }
+            }
+            frame = frame.max(deltas(block, delta[start]).last().unwrap().unwrap_or(0));
+        }
+        let params = (first_read.iter())
+            .filter(|(s, read)| **read && (1..frame).contains(*s))
+            .map(|(s, _)| *s)
+            .max()
+            .unwrap_or(0);
+
+        Self {
+            entry,
+            blocks,
+            delta,
+            frame,
+            params,
+        }
+    }
+
+    fn slot(&self, s: i64) -> String {
+        match s {
+            0 => "ret".to_string(),
+            s if s < 0 => format!("frame[{}]", s),
+            s if s < self.frame => format!("v{}", s),
+            s if s == self.frame => "link".to_string(),
+            s => format!("o{}", s - self.frame),
+        }
+    }
+}
+
+struct Decompiler<'a> {
+    cfg: &'a Cfg,
+    entries: &'a BTreeSet<usize>,
+    params: &'a HashMap<usize, i64>,
+    written: &'a HashSet<usize>,
+    func: &'a Function,
+    visited: HashSet<usize>,
+    loops: Vec<(usize, Option<usize>)>,
+    follows: HashMap<usize, Option<usize>>,
+    ipdom: HashMap<usize, Option<usize>>,
+}
+
+fn negate(cond: &str, negated: bool) -> String {
+    if negated {
+        format!("!{}", cond)
+    } else {
+        cond.to_string()
+    }
+}
+
+fn sum(a: String, b: String) -> String {
+    match (a.as_str(), b.as_str()) {
+        ("0", _) => b,
+        (_, "0") => a,
+        (_, b) if b.starts_with('-') => format!("{} - {}", a, &b[1..]),
+        _ => format!("{} + {}", a, b),
+    }
+}
+
+fn product(a: String, b: String) -> String {
+    match (a.as_str(), b.as_str()) {
+        ("1", _) => b,
+        (_, "1") => a,
+        ("-1", _) => format!("-{}", b),
+        (_, "-1") => format!("-{}", a),
+        _ => format!("{} * {}", a, b),
+    }
+}
+
+impl<'a> Decompiler<'a> {
+    fn new(
+        cfg: &'a Cfg,
+        entries: &'a BTreeSet<usize>,
+        params: &'a HashMap<usize, i64>,
+        written: &'a HashSet<usize>,
+        func: &'a Function,
+    ) -> Self {
+        let mut d = Self {
+            cfg,
+            entries,
+            params,
+            written,
+            func,
+            visited: HashSet::new(),
+            loops: Vec::new(),
+            follows: HashMap::new(),
+            ipdom: HashMap::new(),
+        };
+        d.analyse();
+        d
+    }
+
+    fn succs(&self, start: usize) -> Vec<usize> {
+        successors(&self.cfg.blocks[&start], self.entries)
+            .into_iter()
+            .filter(|s| self.func.blocks.contains(s))
+            .collect()
+    }
+
+    // Finds the natural loops, where each exits to, and the block each branch
+    // joins up again at.
+    fn analyse(&mut self) {
+        let nodes: Vec<usize> = self.func.blocks.iter().copied().collect();
+        let all: BTreeSet<usize> = self.func.blocks.clone();
+        let mut preds: HashMap<usize, Vec<usize>> = HashMap::new();
+        for &n in &nodes {
+            for s in self.succs(n) {
+                preds.entry(s).or_default().push(n);
+            }
+        }
+
+        let entry = self.func.entry;
+        let mut dom: HashMap<usize, BTreeSet<usize>> = (nodes.iter())
+            .map(|&n| {
+                (
+                    n,
+                    if n == entry {
+                        BTreeSet::from([n])
+                    } else {
+                        all.clone()
+                    },
+                )
+            })
+            .collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &n in nodes.iter().filter(|&&n| n != entry) {
+                let mut d = (preds.get(&n).into_iter().flatten())
+                    .map(|p| dom[p].clone())
+                    .reduce(|a, b| &a & &b)
+                    .unwrap_or_default();
+                d.insert(n);
+                if d != dom[&n] {
+                    dom.insert(n, d);
+                    changed = true;
+                }
+            }
+        }
+
+        let mut bodies: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+        let mut back: HashSet<(usize, usize)> = HashSet::new();
+        for &n in &nodes {
+            for h in self.succs(n) {
+                if !dom[&n].contains(&h) {
+                    continue;
+                }
+                back.insert((n, h));
+                let body = bodies.entry(h).or_insert_with(|| BTreeSet::from([h]));
+                let mut work = vec![n];
+                while let Some(m) = work.pop() {
+                    if body.insert(m) {
+                        work.extend(preds.get(&m).into_iter().flatten());
+                    }
+                }
+            }
+        }
+        for (h, body) in &bodies {
+            let exits: BTreeSet<usize> = (body.iter())
+                .flat_map(|&n| self.succs(n))
+                .filter(|s| !body.contains(s))
+                .collect();
+            let own = self.succs(*h).into_iter().find(|s| exits.contains(s));
+            self.follows
+                .insert(*h, own.or_else(|| exits.first().copied()));
+        }
+
+        // Immediate post-dominators with the back edges left out, so the
+        // graph is acyclic. None stands for the function's exit.
+        let mut order = Vec::new();
+        let mut seen = HashSet::new();
+        let mut stack = vec![(entry, false)];
+        while let Some((n, done)) = stack.pop() {
+            if done {
+                order.push(n);
+                continue;
+            }
+            if !seen.insert(n) {
+                continue;
+            }
+            stack.push((n, true));
+            for s in self.succs(n) {
+                if !back.contains(&(n, s)) {
+                    stack.push((s, false));
+                }
+            }
+        }
+        for n in order {
+            let mut chains = (self.succs(n).into_iter())
+                .filter(|s| !back.contains(&(n, *s)))
+                .map(|s| self.chain(s));
+            let join = chains.next().and_then(|first| {
+                let rest: Vec<Vec<usize>> = chains.collect();
+                first
+                    .into_iter()
+                    .find(|a| rest.iter().all(|c| c.contains(a)))
+            });
+            self.ipdom.insert(n, join);
+        }
+    }
+
+    fn chain(&self, mut n: usize) -> Vec<usize> {
+        let mut out = vec![n];
+        while let Some(Some(p)) = self.ipdom.get(&n) {
+            out.push(*p);
+            n = *p;
+        }
+        out
+    }
+
+    fn value(&self, p: Param, delta: Option<i64>) -> String {
+        match (p.mode, delta) {
+            (Mode::Immediate, _) => p.value.to_string(),
+            (Mode::Position, _) => format!("m[{}]", p.value),
+            (Mode::Relative, Some(d)) => self.func.slot(d + p.value),
+            (Mode::Relative, None) => format!("rb[{}]", p.value),
+        }
+    }
+
+    fn end(&self, block: &Block, delta: Option<i64>) -> End {
+        let (_, last) = block.instrs[block.instrs.len() - 1];
+        if last.opcode == Opcode::Hlt {
+            return End::Halt;
+        }
+        let fall = block.exits.iter().find_map(|e| match e {
+            Exit::Fall(t) => Some(*t),
+            _ => None,
+        });
+        if !matches!(last.opcode, Opcode::Jt | Opcode::Jf) {
+            return fall.map_or(End::Stop, End::Next);
+        }
+        if let Some(site) = block.exits.iter().find_map(|e| match e {
+            Exit::Return(t) => Some(*t),
+            _ => None,
+        }) {
+            return End::Call(jumps(block), site);
+        }
+        let target = last.params()[1];
+        if let Some(Exit::Indirect(p)) = block.exits.first() {
+            let returns = p.mode == Mode::Relative && delta.map(|d| d + p.value) == Some(0);
+            return if returns {
+                End::Return
+            } else {
+                End::Indirect(target)
+            };
+        }
+        let taken = jumps(block);
+        match (taken.as_slice(), fall) {
+            _ if never_jumps(&last) => fall.map_or(End::Stop, End::Next),
+            ([t], Some(f)) if !always_jumps(&last) => {
+                End::Branch(last.params()[0], last.opcode == Opcode::Jt, *t, f)
+            }
+            ([t], _) if self.entries.contains(t) && *t != self.func.entry => End::Leave(*t),
+            ([t], _) => End::Next(*t),
+            _ => End::Indirect(target),
+        }
+    }
+
+    // The statements of a block, leaving out the frame set-up and tear-down,
+    // the return address stored for a call, and the final jump.
+    fn body(&self, block: &Block, end: &End, out: &mut Vec<Stmt>) {
+        let ds = deltas(block, self.func.delta[&block.start]);
+        let n = block.instrs.len();
+        for (i, ((_, instr), d)) in block.instrs.iter().zip(&ds).enumerate() {
+            let p = instr.params();
+            let v = |j: usize| self.value(p[j], *d);
+            let skip = match instr.opcode {
+                Opcode::Jt | Opcode::Jf | Opcode::Hlt => i == n - 1,
+                Opcode::Arb => {
+                    let prologue = block.start == self.func.entry
+                        && i == 0
+                        && p[0].mode == Mode::Immediate
+                        && p[0].value == self.func.frame;
+                    let epilogue = matches!(end, End::Return) && i == n - 2;
+                    prologue || epilogue
+                }
+                _ => matches!(end, End::Call(..)) && i == n - 2,
+            };
+            if skip {
+                continue;
+            }
+            let line = match instr.opcode {
+                Opcode::Add => format!("{} = {}", v(2), sum(v(0), v(1))),
+                Opcode::Mul => format!("{} = {}", v(2), product(v(0), v(1))),
+                Opcode::Lt => format!("{} = {} < {}", v(2), v(0), v(1)),
+                Opcode::Eq => format!("{} = {} == {}", v(2), v(0), v(1)),
+                Opcode::In => format!("{} = input()", v(0)),
+                Opcode::Out => format!("output({})", v(0)),
+                Opcode::Arb => format!("rb += {}", v(0)),
+                Opcode::Jt => format!("if {} goto {}", v(0), v(1)),
+                Opcode::Jf => format!("if !{} goto {}", v(0), v(1)),
+                Opcode::Hlt => "halt".to_string(),
+            };
+            out.push(Stmt::Line(line));
+        }
+        if let End::Call(callees, _) = end {
+            let names: Vec<_> = callees.iter().map(|&c| name(c)).collect();
+            let callee = match (self.target_cell(block), names.as_slice()) {
+                (Some(cell), _) if self.written.contains(&cell) => format!("call *[{}]", cell),
+                (_, [one]) => one.clone(),
+                _ => format!("({})", names.join(" | ")),
+            };
+            let count = callees.iter().filter_map(|c| self.params.get(c)).max();
+            let args: Vec<_> = (1..=count.copied().unwrap_or(0))
+                .map(|j| self.func.slot(self.func.frame + j))
+                .collect();
+            out.push(Stmt::Line(format!("{}({})", callee, args.join(", "))));
+        }
+    }
+
+    // The cell a block's final jump reads its target from: its own operand
+    // for an immediate target.
+    fn target_cell(&self, block: &Block) -> Option<usize> {
+        let (addr, jump) = block.instrs[block.instrs.len() - 1];
+        let target = jump.params()[1];
+        match target.mode {
+            Mode::Immediate => Some(addr + 2),
+            Mode::Position => usize::try_from(target.value).ok(),
+            Mode::Relative => None,
+        }
+    }
+
+    // Structures the code from cur until it reaches follow or leaves the
+    // function. entering is set when cur is the header of the loop just
+    // opened.
+    fn seq(&mut self, mut cur: usize, follow: Option<usize>, mut entering: bool) -> Vec<Stmt> {
+        let mut out = Vec::new();
+        loop {
+            if !entering {
+                if Some(cur) == follow {
+                    break;
+                }
+                if let Some(&(h, f)) = self.loops.last() {
+                    if cur == h {
+                        out.push(Stmt::Continue);
+                        break;
+                    }
+                    if Some(cur) == f {
+                        out.push(Stmt::Break);
+                        break;
+                    }
+                }
+                if let Some(&f) = self.follows.get(&cur) {
+                    if !self.visited.contains(&cur) {
+                        self.loops.push((cur, f));
+                        let body = self.seq(cur, None, true);
+                        self.loops.pop();
+                        out.push(Stmt::Loop(body));
+                        match f {
+                            Some(f) => {
+                                cur = f;
+                                continue;
+                            }
+                            None => break,
+                        }
+                    }
+                }
+            }
+            entering = false;
+            if !self.func.blocks.contains(&cur) {
+                // Runs into something that does not decode.
+                out.push(Stmt::Line(format!("goto {}", cur)));
+                break;
+            }
+            if !self.visited.insert(cur) {
+                out.push(Stmt::Goto(cur));
+                break;
+            }
+            out.push(Stmt::Label(cur));
+            let block = &self.cfg.blocks[&cur];
+            let end = self.end(block, *deltas(block, self.func.delta[&cur]).last().unwrap());
+            self.body(block, &end, &mut out);
+            match end {
+                End::Halt => out.push(Stmt::Halt),
+                End::Return => out.push(Stmt::Return),
+                End::Indirect(p) => {
+                    let d = *deltas(block, self.func.delta[&cur]).last().unwrap();
+                    out.push(Stmt::Line(format!("goto *{}", self.value(p, d))));
+                }
+                End::Leave(t) => out.push(Stmt::Line(format!("goto {}", name(t)))),
+                End::Stop => {}
+                End::Call(_, next) | End::Next(next) => {
+                    cur = next;
+                    continue;
+                }
+                End::Branch(test, on_true, taken, fall) => {
+                    let d = *deltas(block, self.func.delta[&cur]).last().unwrap();
+                    let join = self.ipdom.get(&cur).copied().flatten();
+                    let (t, f) = if on_true {
+                        (taken, fall)
+                    } else {
+                        (fall, taken)
+                    };
+                    let then = self.seq(t, join, false);
+                    let els = self.seq(f, join, false);
+                    out.push(Stmt::If {
+                        cond: self.value(test, d),
+                        negated: false,
+                        then,
+                        els,
+                    });
+                    if let Some(j) = join {
+                        cur = j;
+                        continue;
+                    }
+                }
+            }
+            break;
+        }
+        out
+    }
+}
+
+fn gotos(stmts: &[Stmt], out: &mut HashSet<usize>) {
+    for s in stmts {
+        match s {
+            Stmt::Goto(t) => {
+                out.insert(*t);
+            }
+            Stmt::If { then, els, .. } => {
+                gotos(then, out);
+                gotos(els, out);
+            }
+            Stmt::Loop(body) | Stmt::While { body, .. } => gotos(body, out),
+            _ => {}
+        }
+    }
+}
+
+// Drops labels nobody jumps to and rewrites the statements into tidier
+// shapes: an if whose branch ends in a jump loses its else, and a loop that
+// opens with a conditional break becomes a while.
+fn tidy(stmts: Vec<Stmt>, targets: &HashSet<usize>) -> Vec<Stmt> {
+    let mut out = Vec::new();
+    for s in stmts {
+        match s {
+            Stmt::Label(a) if !targets.contains(&a) => {}
+            Stmt::If {
+                cond,
+                negated,
+                then,
+                els,
+            } => {
+                let mut then = tidy(then, targets);
+                let mut els = tidy(els, targets);
+                let mut negated = negated;
+                let short = |a: &Vec<Stmt>, b: &Vec<Stmt>| {
+                    a.last().is_some_and(Stmt::ends_flow)
+                        && (!b.last().is_some_and(Stmt::ends_flow) || a.len() < b.len())
+                };
+                if then.is_empty() || short(&els, &then) {
+                    std::mem::swap(&mut then, &mut els);
+                    negated = !negated;
+                }
+                if then.is_empty() {
+                    continue;
+                }
+                let hoist = then.last().is_some_and(Stmt::ends_flow);
+                let rest = if hoist {
+                    std::mem::take(&mut els)
+                } else {
+                    Vec::new()
+                };
+                out.push(Stmt::If {
+                    cond,
+                    negated,
+                    then,
+                    els,
+                });
+                out.extend(rest);
+            }
+            Stmt::Loop(body) => {
+                let mut body = tidy(body, targets);
+                if body.last() == Some(&Stmt::Continue) {
+                    body.pop();
+                }
+                match body.first() {
+                    Some(Stmt::If {
+                        cond,
+                        negated,
+                        then,
+                        els,
+                    }) if *then == [Stmt::Break] && els.is_empty() => {
+                        out.push(Stmt::While {
+                            cond: cond.clone(),
+                            negated: !negated,
+                            body: body.split_off(1),
+                        });
+                    }
+                    _ => out.push(Stmt::Loop(body)),
+                }
+            }
+            s => out.push(s),
+        }
+    }
+    out
+}
+
+fn render(stmts: &[Stmt], depth: usize, out: &mut String) {
+    let pad = "    ".repeat(depth);
+    for s in stmts {
+        match s {
+            Stmt::Line(l) => writeln!(out, "{}{}", pad, l),
+            Stmt::If {
+                cond,
+                negated,
+                then,
+                els,
+            } => {
+                writeln!(out, "{}if {} {{", pad, negate(cond, *negated)).unwrap();
+                render(then, depth + 1, out);
+                if !els.is_empty() {
+                    writeln!(out, "{}}} else {{", pad).unwrap();
+                    render(els, depth + 1, out);
+                }
+                writeln!(out, "{}}}", pad)
+            }
+            Stmt::Loop(body) => {
+                writeln!(out, "{}loop {{", pad).unwrap();
+                render(body, depth + 1, out);
+                writeln!(out, "{}}}", pad)
+            }
+            Stmt::While {
+                cond,
+                negated,
+                body,
+            } => {
+                writeln!(out, "{}while {} {{", pad, negate(cond, *negated)).unwrap();
+                render(body, depth + 1, out);
+                writeln!(out, "{}}}", pad)
+            }
+            Stmt::Break => writeln!(out, "{}break", pad),
+            Stmt::Continue => writeln!(out, "{}continue", pad),
+            Stmt::Return => writeln!(out, "{}return", pad),
+            Stmt::Halt => writeln!(out, "{}halt", pad),
+            Stmt::Goto(t) => writeln!(out, "{}goto L{}", pad, t),
+            Stmt::Label(a) => writeln!(out, "{}L{}:", "    ".repeat(depth.max(1) - 1), a),
+        }
+        .unwrap();
+    }
+}
+
+// Pseudocode for the program, one function per call target found by
+// control_flow, with loops and ifs recovered from its branches. Code that does
+// not fit those shapes falls back to labels and gotos.
+pub fn decompile(mem: &[i64]) -> String {
+    let cfg = control_flow(mem);
+    let mut entries = BTreeSet::from([0]);
+    for block in cfg.blocks.values().filter(|b| is_call(b)) {
+        entries.extend(jumps(block));
+    }
+    entries.retain(|e| cfg.blocks.contains_key(e));
+    let funcs: Vec<_> = (entries.iter())
+        .map(|&e| Function::new(&cfg, &entries, e))
+        .collect();
+    let params: HashMap<usize, i64> = funcs.iter().map(|f| (f.entry, f.params)).collect();
+    // A call whose target is read from a cell the program writes only goes
+    // where control_flow says if the code is not modified first, so it is
+    // shown as indirect.
+    let written: HashSet<usize> = (cfg.blocks.values())
+        .flat_map(|b| &b.instrs)
+        .filter(|(_, instr)| instr.opcode.writes())
+        .filter_map(|(_, instr)| {
+            let dest = *instr.params().last()?;
+            let cell = usize::try_from(dest.value).ok();
+            cell.filter(|_| dest.mode == Mode::Position)
+        })
+        .collect();
+
+    let mut out = String::new();
+    for func in &funcs {
+        let mut d = Decompiler::new(&cfg, &entries, &params, &written, func);
+        let stmts = d.seq(func.entry, None, false);
+        let mut targets = HashSet::new();
+        gotos(&stmts, &mut targets);
+        let stmts = tidy(stmts, &targets);
+        let args: Vec<_> = (1..=func.params).map(|s| func.slot(s)).collect();
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        writeln!(out, "fn {}({}) {{", name(func.entry), args.join(", ")).unwrap();
+        render(&stmts, 1, &mut out);
+        writeln!(out, "}}").unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble_words;
+
+    #[test]
+    fn loops_and_calls() {
+        // Reads n and outputs n * 2 for each n until it reads a zero.
+        let mem = assemble_words(
+            "
+                ARB #100
+        top:    IN rb+1
+                EQ rb+1, #0, rb+2
+                JT rb+2, #end
+                ADD #back, #0, rb+0
+                JT #1, #double
+        back:   OUT rb+1
+                JT #1, #top
+        end:    HLT
+        double: ARB #3
+                MUL rb-2, #2, rb-2
+                ARB #-3
+                JT #1, rb+0
+            ",
+        )
+        .unwrap();
+        let expected = "\
+fn main() {
+    loop {
+        o1 = input()
+        o2 = o1 == 0
+        if o2 {
+            break
+        }
+        f24(o1)
+        output(o1)
+    }
+    halt
+}
+
+fn f24(v1) {
+    v1 = v1 * 2
+    return
+}
+";
+        assert_eq!(decompile(&mem), expected);
+    }
+
+    #[test]
+    fn self_modifying_call() {
+        // Stores the call target into the jump's own operand before calling.
+        let mem = assemble_words(
+            "
+                ARB #100
+                IN rb+1
+                ADD #double, #0, [call+2]
+                ADD #back, #0, rb+0
+        call:   JT #1, #double
+        back:   OUT rb+1
+                HLT
+        double: ARB #2
+                MUL rb-1, #2, rb-1
+                ARB #-2
+                JT #1, rb+0
+            ",
+        )
+        .unwrap();
+        let expected = "\
+fn main() {
+    o1 = input()
+    m[14] = 18
+    call *[14](o1)
+    output(o1)
+    halt
+}
+
+fn f18(v1) {
+    v1 = v1 * 2
+    return
+}
+";
+        assert_eq!(decompile(&mem), expected);
+    }
+
+    #[test]
+    fn if_else_and_while() {
+        // Counts m[x] down to zero, then outputs 1 if it started odd.
+        let mem = assemble_words(
+            "
+                IN [x]
+                ADD #0, #0, [odd]
+        loop:   JF [x], #done
+                ADD [x], #-1, [x]
+                EQ [odd], #0, [odd]
+                JT #1, #loop
+        done:   JT [odd], #yes
+                OUT #0
+                JT #1, #out
+        yes:    OUT #1
+        out:    HLT
+        x:      .data 0
+        odd:    .data 0
+            ",
+        )
+        .unwrap();
+        let expected = "\
+fn main() {
+    m[31] = input()
+    m[32] = 0
+    while m[31] {
+        m[31] = m[31] - 1
+        m[32] = m[32] == 0
+    }
+    if m[32] {
+        output(1)
+    } else {
+        output(0)
+    }
+    halt
+}
+";
+        assert_eq!(decompile(&mem), expected);
+    }
+}
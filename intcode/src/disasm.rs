@@ -0,0 +1,199 @@
+use crate::instruction::{decode, Instruction, Mode, Opcode};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+const DATA_PER_LINE: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    Instr {
+        addr: usize,
+        instr: Instruction,
+    },
+    Data {
+        addr: usize,
+        values: Vec<i64>,
+        reached: bool,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Listing {
+    pub lines: Vec<Line>,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Line::Instr { addr, instr } => write!(f, "{:>5}: {}", addr, instr),
+            Line::Data {
+                addr,
+                values,
+                reached,
+            } => {
+                let v: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                let note = if *reached { "reached" } else { "unreached" };
+                write!(f, "{:>5}: .data {} ; {}", addr, v.join(", "), note)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+// An unconditional jump is JT with a non-zero or JF with a zero immediate.
+pub(crate) fn always_jumps(instr: &Instruction) -> bool {
+    let cond = instr.params()[0];
+    cond.mode == Mode::Immediate
+        && match instr.opcode {
+            Opcode::Jt => cond.value != 0,
+            Opcode::Jf => cond.value == 0,
+            _ => false,
+        }
+}
+
+pub(crate) fn never_jumps(instr: &Instruction) -> bool {
+    matches!(instr.opcode, Opcode::Jt | Opcode::Jf)
+        && instr.params()[0].mode == Mode::Immediate
+        && !always_jumps(instr)
+}
+
+// The value an ADD or MUL of two immediates stores, if any.
+pub(crate) fn constant_result(instr: &Instruction) -> Option<i64> {
+    let p = instr.params();
+    if !matches!(instr.opcode, Opcode::Add | Opcode::Mul)
+        || p[0].mode != Mode::Immediate
+        || p[1].mode != Mode::Immediate
+    {
+        return None;
+    }
+    match instr.opcode {
+        Opcode::Add => Some(p[0].value + p[1].value),
+        _ => Some(p[0].value * p[1].value),
+    }
+}
+
+// The return address of a call: a constant stored right before an
+// unconditional jump that equals the address after the jump. This covers the
+// call convention compiled programs use.
+pub(crate) fn return_site(mem: &[i64], instr: &Instruction, next: usize) -> Option<usize> {
+    let c = constant_result(instr)?;
+    let jump = decode(mem, next).ok()?;
+    (always_jumps(&jump) && c == (next + jump.size()) as i64).then_some(c as usize)
+}
+
+// Walks control flow from address 0 and returns every instruction reached.
+// Jumps with immediate targets and return sites are followed.
+pub fn reachable_code(mem: &[i64]) -> BTreeMap<usize, Instruction> {
+    let mut code = BTreeMap::new();
+    walk(mem, vec![0], &mut code);
+    code
+}
+
+// Adds the instructions reached from roots to code.
+pub(crate) fn walk(mem: &[i64], roots: Vec<usize>, code: &mut BTreeMap<usize, Instruction>) {
+    let mut work = roots;
+    while let Some(addr) = work.pop() {
+        if code.contains_key(&addr) {
+            continue;
+        }
+        let Ok(instr) = decode(mem, addr) else {
+            continue;
+        };
+        code.insert(addr, instr);
+        let next = addr + instr.size();
+        match instr.opcode {
+            Opcode::Hlt => {}
+            Opcode::Jt | Opcode::Jf => {
+                let target = instr.params()[1];
+                if target.mode == Mode::Immediate && target.value >= 0 && !never_jumps(&instr) {
+                    work.push(target.value as usize);
+                }
+                if !always_jumps(&instr) {
+                    work.push(next);
+                }
+            }
+            _ => {
+                work.extend(return_site(mem, &instr, next));
+                work.push(next);
+            }
+        }
+    }
+}
+
+// Absolute addresses read or written by position-mode operands.
+fn referenced(code: &BTreeMap<usize, Instruction>) -> HashSet<usize> {
+    code.values()
+        .flat_map(|i| i.params().iter())
+        .filter(|p| p.mode == Mode::Position && p.value >= 0)
+        .map(|p| p.value as usize)
+        .collect()
+}
+
+pub fn disassemble(mem: &[i64]) -> Listing {
+    listing(mem, &reachable_code(mem))
+}
+
+// Lists code as instructions and everything else in mem as data.
+pub(crate) fn listing(mem: &[i64], code: &BTreeMap<usize, Instruction>) -> Listing {
+    let refs = referenced(code);
+    let mut lines = Vec::new();
+    let mut addr = 0;
+    while addr < mem.len() {
+        if let Some(instr) = code.get(&addr) {
+            lines.push(Line::Instr {
+                addr,
+                instr: *instr,
+            });
+            addr += instr.size();
+            continue;
+        }
+        let reached = refs.contains(&addr);
+        let start = addr;
+        while addr < mem.len()
+            && addr - start < DATA_PER_LINE
+            && !code.contains_key(&addr)
+            && refs.contains(&addr) == reached
+        {
+            addr += 1;
+        }
+        lines.push(Line::Data {
+            addr: start,
+            values: mem[start..addr].to_vec(),
+            reached,
+        });
+    }
+    Listing { lines }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listing() {
+        let listing = disassemble(&[1, 9, 10, 9, 1105, 1, 8, 7, 99, 5, 6]);
+        let expected = "    0: ADD [9], [10], [9]\n    \
+                        4: JT #1, #8\n    \
+                        7: .data 7 ; unreached\n    \
+                        8: HLT\n    \
+                        9: .data 5, 6 ; reached\n";
+        assert_eq!(listing.to_string(), expected);
+    }
+
+    #[test]
+    fn follows_call_convention() {
+        // ADD #7, #0, rb+0; JF #0, #8; HLT; OUT #1; JF #0, rb+0
+        let mem = [21101, 7, 0, 0, 1106, 0, 8, 99, 104, 1, 2106, 0, 0];
+        let code = reachable_code(&mem);
+        assert!(code.contains_key(&7));
+        assert!(code.contains_key(&10));
+    }
+}
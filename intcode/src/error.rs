@@ -0,0 +1,44 @@
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    InvalidOpcode,
+    InvalidMode(i64),
+    ImmediateWrite,
+    NegativeAddress(i64),
+    ReadPastProgram(usize),
+    // A write at or past the memory limit.
+    AddressTooLarge(usize),
+    // An ADD or MUL result, or a relative address, that does not fit in i64.
+    Overflow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntcodeError {
+    pub pc: usize,
+    pub instr: i64,
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ErrorKind::InvalidOpcode => write!(f, "invalid opcode"),
+            ErrorKind::InvalidMode(m) => write!(f, "invalid parameter mode {}", m),
+            ErrorKind::ImmediateWrite => write!(f, "write in immediate mode"),
+            ErrorKind::NegativeAddress(a) => write!(f, "negative address {}", a),
+            ErrorKind::ReadPastProgram(a) => write!(f, "read past end of program at {}", a),
+            ErrorKind::AddressTooLarge(a) => write!(f, "address {} is past the memory limit", a),
+            ErrorKind::Overflow => write!(f, "arithmetic overflow"),
+        }
+    }
+}
+
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pc {}, instr {}: {}", self.pc, self.instr, self.kind)
+    }
+}
+
+impl Error for IntcodeError {}
@@ -0,0 +1,245 @@
+use crate::ErrorKind;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    Add,
+    Mul,
+    In,
+    Out,
+    Jt,
+    Jf,
+    Lt,
+    Eq,
+    Arb,
+    Hlt,
+}
+
+impl Opcode {
+    pub fn from_code(code: i64) -> Option<Opcode> {
+        match code {
+            1 => Some(Opcode::Add),
+            2 => Some(Opcode::Mul),
+            3 => Some(Opcode::In),
+            4 => Some(Opcode::Out),
+            5 => Some(Opcode::Jt),
+            6 => Some(Opcode::Jf),
+            7 => Some(Opcode::Lt),
+            8 => Some(Opcode::Eq),
+            9 => Some(Opcode::Arb),
+            99 => Some(Opcode::Hlt),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> i64 {
+        match self {
+            Opcode::Add => 1,
+            Opcode::Mul => 2,
+            Opcode::In => 3,
+            Opcode::Out => 4,
+            Opcode::Jt => 5,
+            Opcode::Jf => 6,
+            Opcode::Lt => 7,
+            Opcode::Eq => 8,
+            Opcode::Arb => 9,
+            Opcode::Hlt => 99,
+        }
+    }
+
+    pub fn from_mnemonic(s: &str) -> Option<Opcode> {
+        match s.to_ascii_uppercase().as_str() {
+            "ADD" => Some(Opcode::Add),
+            "MUL" => Some(Opcode::Mul),
+            "IN" => Some(Opcode::In),
+            "OUT" => Some(Opcode::Out),
+            "JT" => Some(Opcode::Jt),
+            "JF" => Some(Opcode::Jf),
+            "LT" => Some(Opcode::Lt),
+            "EQ" => Some(Opcode::Eq),
+            "ARB" => Some(Opcode::Arb),
+            "HLT" => Some(Opcode::Hlt),
+            _ => None,
+        }
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Opcode::Add => "ADD",
+            Opcode::Mul => "MUL",
+            Opcode::In => "IN",
+            Opcode::Out => "OUT",
+            Opcode::Jt => "JT",
+            Opcode::Jf => "JF",
+            Opcode::Lt => "LT",
+            Opcode::Eq => "EQ",
+            Opcode::Arb => "ARB",
+            Opcode::Hlt => "HLT",
+        }
+    }
+
+    pub fn arity(self) -> usize {
+        match self {
+            Opcode::Add | Opcode::Mul | Opcode::Lt | Opcode::Eq => 3,
+            Opcode::Jt | Opcode::Jf => 2,
+            Opcode::In | Opcode::Out | Opcode::Arb => 1,
+            Opcode::Hlt => 0,
+        }
+    }
+
+    // Whether the last parameter is a destination address.
+    pub fn writes(self) -> bool {
+        matches!(
+            self,
+            Opcode::Add | Opcode::Mul | Opcode::In | Opcode::Lt | Opcode::Eq
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+impl Mode {
+    pub fn from_digit(d: i64) -> Option<Mode> {
+        match d {
+            0 => Some(Mode::Position),
+            1 => Some(Mode::Immediate),
+            2 => Some(Mode::Relative),
+            _ => None,
+        }
+    }
+
+    pub fn digit(self) -> i64 {
+        match self {
+            Mode::Position => 0,
+            Mode::Immediate => 1,
+            Mode::Relative => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Param {
+    pub mode: Mode,
+    pub value: i64,
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            Mode::Position => write!(f, "[{}]", self.value),
+            Mode::Immediate => write!(f, "#{}", self.value),
+            Mode::Relative if self.value < 0 => write!(f, "rb{}", self.value),
+            Mode::Relative => write!(f, "rb+{}", self.value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Instruction {
+    pub opcode: Opcode,
+    params: [Param; 3],
+}
+
+impl Instruction {
+    pub fn new(opcode: Opcode, params: &[Param]) -> Self {
+        let mut p = [Param {
+            mode: Mode::Position,
+            value: 0,
+        }; 3];
+        p[..params.len()].copy_from_slice(params);
+        Self { opcode, params: p }
+    }
+
+    pub fn params(&self) -> &[Param] {
+        &self.params[..self.opcode.arity()]
+    }
+
+    pub fn size(&self) -> usize {
+        self.opcode.arity() + 1
+    }
+
+    pub fn encode(&self) -> Vec<i64> {
+        let mut instr = self.opcode.code();
+        let mut scale = 100;
+        for p in self.params() {
+            instr += p.mode.digit() * scale;
+            scale *= 10;
+        }
+        let mut v = vec![instr];
+        v.extend(self.params().iter().map(|p| p.value));
+        v
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.opcode.mnemonic())?;
+        for (i, p) in self.params().iter().enumerate() {
+            let sep = if i == 0 { " " } else { ", " };
+            write!(f, "{}{}", sep, p)?;
+        }
+        Ok(())
+    }
+}
+
+// Splits an instruction word into its opcode and parameter modes.
+pub fn decode_op(instr: i64) -> Result<(Opcode, [Mode; 3]), ErrorKind> {
+    if instr < 0 {
+        return Err(ErrorKind::InvalidOpcode);
+    }
+    let opcode = Opcode::from_code(instr % 100).ok_or(ErrorKind::InvalidOpcode)?;
+    let mut modes = [Mode::Position; 3];
+    let mut rest = instr / 100;
+    for m in modes.iter_mut().take(opcode.arity()) {
+        let d = rest % 10;
+        *m = Mode::from_digit(d).ok_or(ErrorKind::InvalidMode(d))?;
+        rest /= 10;
+    }
+    if opcode.writes() && modes[opcode.arity() - 1] == Mode::Immediate {
+        return Err(ErrorKind::ImmediateWrite);
+    }
+    Ok((opcode, modes))
+}
+
+// Decodes the instruction at addr; words past the end of mem are an error.
+pub fn decode(mem: &[i64], addr: usize) -> Result<Instruction, ErrorKind> {
+    let word = |a: usize| mem.get(a).copied().ok_or(ErrorKind::ReadPastProgram(a));
+    let (opcode, modes) = decode_op(word(addr)?)?;
+    let mut params = [Param {
+        mode: Mode::Position,
+        value: 0,
+    }; 3];
+    for (i, p) in params.iter_mut().take(opcode.arity()).enumerate() {
+        *p = Param {
+            mode: modes[i],
+            value: word(addr + 1 + i)?,
+        };
+    }
+    Ok(Instruction { opcode, params })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_modes() {
+        let i = decode(&[21101, 4, -2, 7], 0).unwrap();
+        assert_eq!(i.opcode, Opcode::Add);
+        assert_eq!(i.to_string(), "ADD #4, #-2, rb+7");
+        assert_eq!(i.encode(), vec![21101, 4, -2, 7]);
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert_eq!(decode(&[42], 0), Err(ErrorKind::InvalidOpcode));
+        assert_eq!(decode(&[301, 0, 0, 0], 0), Err(ErrorKind::InvalidMode(3)));
+        assert_eq!(decode(&[11101, 0, 0, 0], 0), Err(ErrorKind::ImmediateWrite));
+        assert_eq!(decode(&[1, 0], 0), Err(ErrorKind::ReadPastProgram(2)));
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::mpsc::{Receiver, Sender};
+
+// Where IN instructions get their values. None means no value is available
+// yet: the machine stops with RunState::NeedsInput and retries the same
+// instruction when run again.
+pub trait InputSource {
+    fn next_input(&mut self) -> Option<i64>;
+}
+
+// Where OUT instructions send their values.
+pub trait OutputSink {
+    fn send_output(&mut self, value: i64);
+}
+
+impl InputSource for VecDeque<i64> {
+    fn next_input(&mut self) -> Option<i64> {
+        self.pop_front()
+    }
+}
+
+impl OutputSink for VecDeque<i64> {
+    fn send_output(&mut self, value: i64) {
+        self.push_back(value);
+    }
+}
+
+impl<F: FnMut() -> Option<i64>> InputSource for F {
+    fn next_input(&mut self) -> Option<i64> {
+        self()
+    }
+}
+
+impl<F: FnMut(i64)> OutputSink for F {
+    fn send_output(&mut self, value: i64) {
+        self(value)
+    }
+}
+
+// Blocks until a value arrives. Once every sender has gone the machine waits
+// for input instead.
+impl InputSource for Receiver<i64> {
+    fn next_input(&mut self) -> Option<i64> {
+        self.recv().ok()
+    }
+}
+
+// Values sent after the receiver has gone are dropped.
+impl OutputSink for Sender<i64> {
+    fn send_output(&mut self, value: i64) {
+        let _ = self.send(value);
+    }
+}
+
+// Feeds the bytes of a reader to the machine as ASCII codes, for example a
+// script file or stdin. End of input leaves the machine waiting for input.
+pub struct AsciiInput<R> {
+    reader: R,
+}
+
+impl<R: Read> AsciiInput<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+}
+
+impl<R: Read> InputSource for AsciiInput<R> {
+    fn next_input(&mut self) -> Option<i64> {
+        let mut byte = [0];
+        match self.reader.read(&mut byte) {
+            Ok(1) => Some(byte[0] as i64),
+            _ => None,
+        }
+    }
+}
+
+// Writes output as ASCII text. Values outside the ASCII range, such as a
+// final answer, are written as decimal numbers on a line of their own.
+pub struct AsciiOutput<W> {
+    writer: W,
+}
+
+impl<W: Write> AsciiOutput<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> OutputSink for AsciiOutput<W> {
+    fn send_output(&mut self, value: i64) {
+        let _ = match u8::try_from(value) {
+            Ok(b) if b.is_ascii() => self.writer.write_all(&[b]),
+            _ => writeln!(self.writer, "{}", value),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Intcode as Computer, RunState};
+    use std::cell::Cell;
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    #[test]
+    fn closures() {
+        let mut inputs = vec![1, 2, 3, 4, 0].into_iter();
+        let total = Cell::new(0);
+        // Outputs the sum of each pair of inputs until it reads a zero.
+        let s = "3, 20, 1006, 20, 16, 3, 21, 1, 20, 21, 22, 4, 22, 1105, 1, 0, 99".to_string();
+        let comp = Computer::intcode_instance(s);
+        let mut comp = comp.with_io(|| inputs.next(), |v| total.set(total.get() + v));
+        assert_eq!(comp.run().unwrap(), RunState::Halted);
+        assert_eq!(total.get(), 10);
+    }
+
+    #[test]
+    fn closure_waits_for_input() {
+        // Outputs the sum of each pair of inputs until it reads a zero.
+        let s = "3, 20, 1006, 20, 16, 3, 21, 1, 20, 21, 22, 4, 22, 1105, 1, 0, 99".to_string();
+        let comp = Computer::intcode_instance(s);
+        let mut comp = comp.with_io(|| None, |_| {});
+        assert_eq!(comp.run().unwrap(), RunState::NeedsInput);
+        assert_eq!(comp.pc(), 0);
+        assert!(comp.awaiting_input());
+    }
+
+    #[test]
+    fn channels() {
+        let (in_tx, in_rx) = channel();
+        let (out_tx, out_rx) = channel();
+        // Outputs the sum of each pair of inputs until it reads a zero.
+        let s = "3, 20, 1006, 20, 16, 3, 21, 1, 20, 21, 22, 4, 22, 1105, 1, 0, 99".to_string();
+        let comp = Computer::intcode_instance(s);
+        let mut comp = comp.with_io(in_rx, out_tx);
+        let handle = thread::spawn(move || comp.run().unwrap());
+        for v in [5, 6, 7, 8, 0] {
+            in_tx.send(v).unwrap();
+        }
+        assert_eq!(handle.join().unwrap(), RunState::Halted);
+        assert_eq!(out_rx.iter().collect::<Vec<_>>(), vec![11, 15]);
+    }
+
+    #[test]
+    fn ascii_reader_and_writer() {
+        // Echoes input back until it reads a newline.
+        let echo = "3, 100, 4, 100, 1008, 100, 10, 101, 1006, 101, 0, 104, 1000, 99";
+        let comp = Computer::intcode_instance(echo.to_string());
+        let mut comp = comp.with_io(
+            AsciiInput::new("hi\nignored".as_bytes()),
+            AsciiOutput::new(Vec::new()),
+        );
+        assert_eq!(comp.run().unwrap(), RunState::Halted);
+        let (_, output) = comp.into_io();
+        assert_eq!(
+            String::from_utf8(output.into_inner()).unwrap(),
+            "hi\n1000\n"
+        );
+    }
+
+    #[test]
+    fn run_until_output_takes_the_value() {
+        let mut out = VecDeque::new();
+        // Outputs the sum of each pair of inputs until it reads a zero.
+        let s = "3, 20, 1006, 20, 16, 3, 21, 1, 20, 21, 22, 4, 22, 1105, 1, 0, 99".to_string();
+        let comp = Computer::intcode_instance(s);
+        let mut comp = comp.with_io(VecDeque::from([1, 2, 3, 4]), |v| out.push_back(v));
+        assert_eq!(comp.run_until_output().unwrap(), RunState::Output(3));
+        assert_eq!(comp.run().unwrap(), RunState::NeedsInput);
+        drop(comp);
+        assert_eq!(out, vec![7]);
+    }
+}
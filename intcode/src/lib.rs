@@ -1,324 +1,637 @@
-use nohash_hasher::IntMap;
-use std::collections::VecDeque;
+use memory::{Memory, MEMORY_LIMIT};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use watch::Watchpoints;
 
-pub struct Intcode {
+mod ascii;
+mod asm;
+mod cfg;
+mod coverage;
+mod decompile;
+mod disasm;
+mod error;
+mod instruction;
+mod io;
+mod memory;
+mod network;
+mod patch;
+mod pipeline;
+mod profile;
+mod program;
+mod runtime;
+mod snapshot;
+mod symbolic;
+mod trace;
+mod watch;
+
+pub use ascii::{AsciiError, AsciiIntcode};
+pub use asm::{assemble, assemble_words, AsmError, AsmErrorKind};
+pub use cfg::{control_flow, Block, Cfg, Exit};
+pub use coverage::Coverage;
+pub use decompile::decompile;
+pub use disasm::{disassemble, reachable_code, Line, Listing};
+pub use error::{ErrorKind, IntcodeError};
+pub use instruction::{decode, decode_op, Instruction, Mode, Opcode, Param};
+pub use io::{AsciiInput, AsciiOutput, InputSource, OutputSink};
+pub use network::{FirstPacket, Nat, Network, Packet};
+pub use patch::{Edit, Patch, PatchError, PatchErrorKind};
+pub use pipeline::{phase_search, Pipeline};
+pub use profile::Profile;
+pub use program::{ProgramError, BINARY_MAGIC};
+pub use runtime::{NoInput, Outcome, Route, Runtime, RuntimeError, Stop};
+pub use snapshot::{Snapshot, SnapshotError, SNAPSHOT_VERSION};
+pub use symbolic::{Affine, Assumption, Symbolic};
+pub use trace::{Trace, TraceBuffer, TraceRecord};
+pub use watch::{Access, Action, IoEvent, WatchEvent};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunState {
+    Halted,
+    NeedsInput,
+    Output(i64),
+    // Stopped by run_for or the step limit, with the machine's step count.
+    BudgetExhausted(u64),
+    // A watchpoint or hook asked to pause.
+    Paused,
+}
+
+// What an executed instruction read and wrote, for the trace.
+#[derive(Default)]
+struct Effect {
+    operands: [i64; 2],
+    count: usize,
+    dest: Option<usize>,
+    value: Option<i64>,
+}
+
+impl Effect {
+    fn operand(v: i64) -> Self {
+        Self {
+            operands: [v, 0],
+            count: 1,
+            ..Self::default()
+        }
+    }
+}
+
+// A machine reads IN values from I and sends OUT values to O. Both default to
+// queues, which the methods on Intcode without parameters manage directly.
+#[derive(Clone)]
+pub struct Intcode<I = VecDeque<i64>, O = VecDeque<i64>> {
     pc: usize,
-    rb: usize,
-    program: IntMap<usize, i64>,
-    input: i64,
-    input_ready: bool,
-    output: VecDeque<i64>,
+    rb: i64,
+    memory: Memory,
+    input: I,
+    output: O,
     halted: bool,
-    debug: bool,
-    orig_program: String,
+    trace: Option<Trace>,
+    profile: Option<Box<Profile>>,
+    watch: Option<Box<Watchpoints>>,
     awaiting_input: bool,
+    steps: u64,
+    step_limit: Option<u64>,
+    snapshots: HashMap<String, Snapshot>,
 }
 
-fn parse_program(p: String) -> IntMap<usize, i64> {
-    let program: IntMap<usize, i64> = p
-        .split(',')
-        .map(|a| a.trim().parse::<i64>().unwrap())
-        .enumerate()
-        .collect();
-    program
+fn address(a: i64) -> Result<usize, ErrorKind> {
+    if a < 0 {
+        Err(ErrorKind::NegativeAddress(a))
+    } else {
+        Ok(a as usize)
+    }
 }
 
 impl Intcode {
+    // Panics on a malformed program; from_str says what is wrong instead.
     pub fn intcode_instance(p: String) -> Self {
+        p.parse().unwrap_or_else(|e| panic!("bad program: {}", e))
+    }
+
+    // Reads a text program, or a binary one as written by to_binary.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, ProgramError> {
+        let values = program::parse_bytes(&fs::read(path)?)?;
+        Ok(Self::from_image(Arc::from(values)))
+    }
+
+    // Machines made from the same image share it until they write to it.
+    pub fn from_image(image: Arc<[i64]>) -> Self {
         Self {
             pc: 0,
             rb: 0,
-            program: parse_program(p.clone()),
-            input: 0,
-            input_ready: false,
+            memory: Memory::new(image),
+            input: VecDeque::new(),
             output: VecDeque::new(),
             halted: false,
-            debug: false,
-            orig_program: p,
+            trace: None,
+            profile: None,
+            watch: None,
             awaiting_input: false,
+            steps: 0,
+            step_limit: None,
+            snapshots: HashMap::new(),
+        }
+    }
+
+    pub fn from_snapshot(snapshot: &Snapshot) -> Self {
+        let mut comp = Self::from_image(Arc::from(snapshot.memory.as_slice()));
+        comp.restore_snapshot(snapshot);
+        comp
+    }
+
+    pub fn set_input(&mut self, data: i64) {
+        self.input.clear();
+        self.push_input(data);
+    }
+
+    pub fn push_input(&mut self, data: i64) {
+        self.input.push_back(data);
+        self.awaiting_input = false;
+    }
+
+    pub fn extend_input<I: IntoIterator<Item = i64>>(&mut self, data: I) {
+        self.input.extend(data);
+        self.awaiting_input = self.awaiting_input && self.input.is_empty();
+    }
+
+    pub fn get_output(&mut self) -> i64 {
+        self.output.pop_front().unwrap()
+    }
+
+    pub fn get_last_output(&mut self) -> i64 {
+        self.output.pop_back().unwrap()
+    }
+
+    pub fn get_outputs(&mut self) -> Vec<i64> {
+        let mut v: Vec<i64> = Vec::new();
+        for n in &self.output {
+            v.push(*n);
+        }
+        self.output.clear();
+        v
+    }
+
+    // Queued output that has not been taken yet.
+    pub fn peek_outputs(&self) -> Vec<i64> {
+        self.output.iter().copied().collect()
+    }
+
+    pub fn peek_inputs(&self) -> Vec<i64> {
+        self.input.iter().copied().collect()
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            pc: self.pc,
+            rb: self.rb,
+            memory: self.memory(),
+            input: self.input.iter().copied().collect(),
+            output: self.output.iter().copied().collect(),
+            halted: self.halted,
+            awaiting_input: self.awaiting_input,
+            steps: self.steps,
         }
     }
 
-    pub fn run(&mut self) -> bool {
+    pub fn restore_snapshot(&mut self, snapshot: &Snapshot) {
+        self.pc = snapshot.pc;
+        self.rb = snapshot.rb;
+        self.memory.load(&snapshot.memory);
+        self.input = snapshot.input.iter().copied().collect();
+        self.output = snapshot.output.iter().copied().collect();
+        self.halted = snapshot.halted;
+        self.awaiting_input = snapshot.awaiting_input;
+        self.steps = snapshot.steps;
+    }
+
+    pub fn save_named(&mut self, name: &str) {
+        let snapshot = self.snapshot();
+        self.snapshots.insert(name.to_string(), snapshot);
+    }
+
+    // Returns false if no snapshot has that name.
+    pub fn restore_named(&mut self, name: &str) -> bool {
+        match self.snapshots.get(name).cloned() {
+            Some(snapshot) => {
+                self.restore_snapshot(&snapshot);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl FromStr for Intcode {
+    type Err = ProgramError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from_image(Arc::from(program::parse_text(s)?)))
+    }
+}
+
+impl<I: InputSource, O: OutputSink> Intcode<I, O> {
+    // The same machine reading from input and writing to output instead.
+    // Anything still queued in the old input or output is dropped, as are
+    // named snapshots.
+    pub fn with_io<I2: InputSource, O2: OutputSink>(
+        self,
+        input: I2,
+        output: O2,
+    ) -> Intcode<I2, O2> {
+        Intcode {
+            pc: self.pc,
+            rb: self.rb,
+            memory: self.memory,
+            input,
+            output,
+            halted: self.halted,
+            trace: self.trace,
+            profile: self.profile,
+            watch: self.watch,
+            awaiting_input: false,
+            steps: self.steps,
+            step_limit: self.step_limit,
+            snapshots: HashMap::new(),
+        }
+    }
+
+    pub fn into_io(self) -> (I, O) {
+        (self.input, self.output)
+    }
+
+    pub fn source(&self) -> &I {
+        &self.input
+    }
+
+    pub fn source_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    pub fn sink(&self) -> &O {
+        &self.output
+    }
+
+    pub fn sink_mut(&mut self) -> &mut O {
+        &mut self.output
+    }
+
+    pub fn run(&mut self) -> Result<RunState, IntcodeError> {
+        self.run_until(false, None)
+    }
+
+    // Like run, but executes at most max_steps instructions.
+    pub fn run_for(&mut self, max_steps: u64) -> Result<RunState, IntcodeError> {
+        self.run_until(false, Some(max_steps))
+    }
+
+    // Like run, but stops at the next OUT and hands its value back as
+    // RunState::Output instead of sending it to the output.
+    pub fn run_until_output(&mut self) -> Result<RunState, IntcodeError> {
+        self.run_until(true, None)
+    }
+
+    fn run_until(
+        &mut self,
+        stop_on_output: bool,
+        max_steps: Option<u64>,
+    ) -> Result<RunState, IntcodeError> {
+        let limit = match (max_steps.map(|n| self.steps + n), self.step_limit) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
         loop {
-            let instr = self.program[&self.pc];
-            if self.debug {
-                println!("pc: {}, instr: {}", self.pc, instr);
+            // A halted machine stays put rather than running its HLT again.
+            if self.halted {
+                return Ok(RunState::Halted);
             }
-            let padded: String = format!("{instr:05}");
-            let op = padded[3..].to_owned().parse::<i32>().unwrap();
-            let mut modes = padded[..3].to_owned();
-
-            match op {
-                1 | 2 | 7 | 8 => self.three_args(op, &mut modes),
-                3 => {
-                    if self.input_ready {
-                        self.one_arg(op, modes.pop().unwrap());
-                    } else {
-                        return false;
-                    }
-                }
-                4 | 9 => self.one_arg(op, modes.pop().unwrap()),
-                5 | 6 => self.two_args(op, &mut modes),
-                99 => {
-                    if self.debug {
-                        println!("Halt");
-                    }
-                    self.halted = true;
-                    return true;
-                }
-                _ => println!(r#"Invalid opcode"#),
+            if limit.is_some_and(|l| self.steps >= l) {
+                return Ok(RunState::BudgetExhausted(self.steps));
+            }
+            if let Some(v) = self.step_with(stop_on_output)? {
+                return Ok(RunState::Output(v));
+            }
+            if self.paused() {
+                return Ok(RunState::Paused);
+            }
+            if self.awaiting_input {
+                return Ok(RunState::NeedsInput);
             }
         }
     }
 
-    pub fn step(&mut self) {
-        let instr = self.program[&self.pc];
-        if self.debug {
-            println!("pc: {}, instr: {}", self.pc, instr);
+    pub fn step(&mut self) -> Result<(), IntcodeError> {
+        self.step_with(false).map(|_| ())
+    }
+
+    fn step_with(&mut self, capture: bool) -> Result<Option<i64>, IntcodeError> {
+        let pc = self.pc;
+        if let Some(watch) = &mut self.watch {
+            watch.start(pc);
         }
-        let padded: String = format!("{instr:05}");
-        let op = padded[3..].to_owned().parse::<i32>().unwrap();
-        let mut modes = padded[..3].to_owned();
+        self.execute(capture).map_err(|kind| IntcodeError {
+            pc,
+            instr: self.memory.get(pc).unwrap_or_default(),
+            kind,
+        })
+    }
 
-        match op {
-            1 | 2 | 7 | 8 => self.three_args(op, &mut modes),
-            3 => {
-                if self.input_ready {
-                    self.one_arg(op, modes.pop().unwrap());
-                } else {
+    // Returns the value of an OUT when capture is set rather than sending it.
+    fn execute(&mut self, capture: bool) -> Result<Option<i64>, ErrorKind> {
+        let (pc, rb) = (self.pc, self.rb);
+        let (op, modes) = decode_op(self.fetch(pc)?)?;
+
+        let mut captured = None;
+        let effect = match op {
+            Opcode::Add | Opcode::Mul | Opcode::Lt | Opcode::Eq => self.three_args(op, modes)?,
+            Opcode::In => match self.input.next_input() {
+                Some(val) => {
+                    self.awaiting_input = false;
+                    if let Some(watch) = &mut self.watch {
+                        watch.input(val);
+                    }
+                    self.get_input(val, modes[0])?
+                }
+                None => {
                     self.awaiting_input = true;
+                    return Ok(None);
                 }
-            }
-            4 | 9 => self.one_arg(op, modes.pop().unwrap()),
-            5 | 6 => self.two_args(op, &mut modes),
-            99 => {
-                if self.debug {
-                    println!("Halt");
+            },
+            Opcode::Out => {
+                let effect = self.one_arg(op, modes[0])?;
+                let val = effect.operands[0];
+                if let Some(watch) = &mut self.watch {
+                    watch.output(val);
                 }
+                if capture {
+                    captured = Some(val);
+                } else {
+                    self.output.send_output(val);
+                }
+                effect
+            }
+            Opcode::Arb => self.one_arg(op, modes[0])?,
+            Opcode::Jt | Opcode::Jf => self.two_args(op, modes)?,
+            Opcode::Hlt => {
                 self.halted = true;
+                Effect::default()
             }
-            _ => println!(r#"Invalid opcode"#),
+        };
+        self.steps += 1;
+        if let Some(profile) = &mut self.profile {
+            profile.execute(pc, op);
+        }
+        if let Some(trace) = &self.trace {
+            trace.record(&TraceRecord {
+                pc,
+                opcode: op.mnemonic().to_string(),
+                modes: modes[..op.arity()].iter().map(|m| m.digit()).collect(),
+                operands: effect.operands[..effect.count].to_vec(),
+                dest: effect.dest,
+                value: effect.value,
+                rb,
+            });
         }
+        Ok(captured)
     }
 
-    fn three_args(&mut self, op: i32, modes: &mut String) {
+    fn fetch(&self, pos: usize) -> Result<i64, ErrorKind> {
+        self.memory.get(pos).ok_or(ErrorKind::ReadPastProgram(pos))
+    }
+
+    fn three_args(&mut self, op: Opcode, modes: [Mode; 3]) -> Result<Effect, ErrorKind> {
         self.pc += 1;
-        let mut mode = modes.pop().unwrap();
-        let arg1 = self.get_value(self.program[&self.pc], mode);
+        let arg1 = self.get_value(self.fetch(self.pc)?, modes[0])?;
         self.pc += 1;
-        mode = modes.pop().unwrap();
-        let arg2 = self.get_value(self.program[&self.pc], mode);
+        let arg2 = self.get_value(self.fetch(self.pc)?, modes[1])?;
         self.pc += 1;
-        mode = modes.pop().unwrap();
-        let mut dest = self.program[&self.pc];
-        dest = self.get_dest(dest, mode);
+        let dest = self.get_dest(self.fetch(self.pc)?, modes[2])?;
         self.pc += 1;
-        if self.debug {
-            println!("op: {}, arg1: {}, arg2: {}, dest: {}", op, arg1, arg2, dest);
-        }
-        match op {
-            1 => self.add(arg1, arg2, dest),
-            2 => self.mul(arg1, arg2, dest),
-            7 => self.less_than(arg1, arg2, dest),
-            8 => self.equals(arg1, arg2, dest),
-            _ => println!("Unsupported operation"),
-        }
+        let val = match op {
+            Opcode::Add => arg1.checked_add(arg2).ok_or(ErrorKind::Overflow)?,
+            Opcode::Mul => arg1.checked_mul(arg2).ok_or(ErrorKind::Overflow)?,
+            Opcode::Lt => (arg1 < arg2) as i64,
+            Opcode::Eq => (arg1 == arg2) as i64,
+            _ => return Err(ErrorKind::InvalidOpcode),
+        };
+        self.store(dest, val)?;
+        Ok(Effect {
+            operands: [arg1, arg2],
+            count: 2,
+            dest: Some(dest),
+            value: Some(val),
+        })
     }
 
-    fn two_args(&mut self, op: i32, modes: &mut String) {
+    fn two_args(&mut self, op: Opcode, modes: [Mode; 3]) -> Result<Effect, ErrorKind> {
         self.pc += 1;
-        let mut mode = modes.pop().unwrap();
-        let arg1 = self.get_value(self.program[&self.pc], mode);
+        let arg1 = self.get_value(self.fetch(self.pc)?, modes[0])?;
         self.pc += 1;
-        mode = modes.pop().unwrap();
-        let arg2 = self.get_value(self.program[&self.pc], mode);
+        let arg2 = self.get_value(self.fetch(self.pc)?, modes[1])?;
         self.pc += 1;
-        if self.debug {
-            println!("op: {}, arg1: {}, arg2: {}", op, arg1, arg2);
-        }
         match op {
-            5 => self.jump_true(arg1, arg2),
-            6 => self.jump_false(arg1, arg2),
-            _ => println!("Unsupported operation"),
+            Opcode::Jt => self.jump_true(arg1, arg2)?,
+            Opcode::Jf => self.jump_false(arg1, arg2)?,
+            _ => return Err(ErrorKind::InvalidOpcode),
         }
+        Ok(Effect {
+            operands: [arg1, arg2],
+            count: 2,
+            ..Effect::default()
+        })
     }
 
-    fn one_arg(&mut self, op: i32, mode: char) {
+    fn one_arg(&mut self, op: Opcode, mode: Mode) -> Result<Effect, ErrorKind> {
         self.pc += 1;
-        let arg = self.program[&self.pc];
+        let arg = self.fetch(self.pc)?;
         self.pc += 1;
-        if self.debug {
-            println!("op: {}, arg: {}, mode: {}", op, arg, mode);
-        }
+        let val = self.get_value(arg, mode)?;
         match op {
-            3 => {
-                let dest: i64 = self.get_dest(arg, mode);
-                self.get_input(dest);
-            }
-            4 => {
-                let val = self.get_value(arg, mode);
-                self.set_output(val);
+            Opcode::Out => Ok(Effect::operand(val)),
+            Opcode::Arb => {
+                self.adjust_rb(val)?;
+                Ok(Effect {
+                    value: Some(self.rb),
+                    ..Effect::operand(val)
+                })
             }
-            9 => {
-                let v = self.get_value(arg, mode);
-                self.adjust_rb(v)
-            }
-            _ => println!("Unsupported operation"),
+            _ => Err(ErrorKind::InvalidOpcode),
         }
     }
 
-    fn get_value(&mut self, arg: i64, mode: char) -> i64 {
+    fn get_input(&mut self, val: i64, mode: Mode) -> Result<Effect, ErrorKind> {
+        self.pc += 1;
+        let dest = self.get_dest(self.fetch(self.pc)?, mode)?;
+        self.pc += 1;
+        self.store(dest, val)?;
+        Ok(Effect {
+            dest: Some(dest),
+            value: Some(val),
+            ..Effect::default()
+        })
+    }
+
+    fn get_value(&mut self, arg: i64, mode: Mode) -> Result<i64, ErrorKind> {
         match mode {
-            '0' => self.read(arg as usize),
-            '1' => arg,
-            '2' => {
-                let tmp = self.rb as i64 + arg;
-                if self.debug {
-                    let result = self.read(tmp as usize);
-                    println!(
-                        "  rb: {}, arg: {} -> rb: {} = {}",
-                        self.rb, arg, tmp, result
-                    );
-                }
-                self.read(tmp as usize)
-            }
-            _ => panic!("Invalid mode"),
+            Mode::Position => Ok(self.load(address(arg)?)),
+            Mode::Immediate => Ok(arg),
+            Mode::Relative => Ok(self.load(self.relative(arg)?)),
         }
     }
 
-    fn get_dest(&mut self, arg: i64, mode: char) -> i64 {
+    fn get_dest(&mut self, arg: i64, mode: Mode) -> Result<usize, ErrorKind> {
         match mode {
-            '0' | '1' => arg,
-            '2' => self.rb as i64 + arg,
-            _ => {
-                println!("Unsupported mode for destination");
-                -1
-            }
+            Mode::Position => address(arg),
+            Mode::Immediate => Err(ErrorKind::ImmediateWrite),
+            Mode::Relative => self.relative(arg),
         }
     }
 
-    fn add(&mut self, arg1: i64, arg2: i64, dest: i64) {
-        if self.debug {
-            println!("{} + {} = {} -> {}\n", arg1, arg2, arg1 + arg2, dest);
-        }
-        self.write(arg1 + arg2, dest);
+    #[inline]
+    fn relative(&self, arg: i64) -> Result<usize, ErrorKind> {
+        address(self.rb.checked_add(arg).ok_or(ErrorKind::Overflow)?)
     }
 
-    fn mul(&mut self, arg1: i64, arg2: i64, dest: i64) {
-        if self.debug {
-            println!("{} * {} = {} -> {}\n", arg1, arg2, arg1 * arg2, dest);
+    fn jump_true(&mut self, arg1: i64, arg2: i64) -> Result<(), ErrorKind> {
+        if arg1 != 0 {
+            self.pc = address(arg2)?;
         }
-        self.write(arg1 * arg2, dest);
+        Ok(())
     }
 
-    fn less_than(&mut self, arg1: i64, arg2: i64, dest: i64) {
-        if self.debug {
-            println!("{} < {} -> {}\n", arg1, arg2, dest);
-        }
-        if arg1 < arg2 {
-            self.write(1, dest);
-        } else {
-            self.write(0, dest);
+    fn jump_false(&mut self, arg1: i64, arg2: i64) -> Result<(), ErrorKind> {
+        if arg1 == 0 {
+            self.pc = address(arg2)?;
         }
+        Ok(())
     }
 
-    fn equals(&mut self, arg1: i64, arg2: i64, dest: i64) {
-        if self.debug {
-            println!("{} == {} -> {}\n", arg1, arg2, dest);
-        }
-        if arg1 == arg2 {
-            self.write(1, dest);
-        } else {
-            self.write(0, dest);
-        }
+    fn adjust_rb(&mut self, arg: i64) -> Result<(), ErrorKind> {
+        self.rb = self.rb.checked_add(arg).ok_or(ErrorKind::Overflow)?;
+        Ok(())
     }
 
-    fn jump_true(&mut self, arg1: i64, arg2: i64) {
-        if self.debug {
-            println!("{} != 0 -> jump to {}\n", arg1, arg2);
+    // Reads and writes made by instructions, as opposed to the public read and
+    // write, so that the profile only counts the program's own traffic.
+    fn load(&mut self, pos: usize) -> i64 {
+        if let Some(profile) = &mut self.profile {
+            profile.read(pos);
         }
-        if arg1 != 0 {
-            self.pc = arg2 as usize;
+        let val = self.memory.get(pos).unwrap_or_default();
+        if let Some(watch) = &mut self.watch {
+            watch.access(pos, Access::Read, val, val);
         }
+        val
     }
 
-    fn jump_false(&mut self, arg1: i64, arg2: i64) {
-        if self.debug {
-            println!("{} == 0 -> jump to {}\n", arg1, arg2);
+    fn store(&mut self, pos: usize, val: i64) -> Result<(), ErrorKind> {
+        if pos >= MEMORY_LIMIT {
+            return Err(ErrorKind::AddressTooLarge(pos));
         }
-        if arg1 == 0 {
-            self.pc = arg2 as usize;
+        if let Some(profile) = &mut self.profile {
+            profile.write(pos);
         }
+        if let Some(watch) = &mut self.watch {
+            let old = self.memory.get(pos).unwrap_or_default();
+            watch.access(pos, Access::Write, old, val);
+        }
+        self.memory.set(pos, val)
     }
 
-    fn get_input(&mut self, dest: i64) {
-        self.write(self.input, dest);
-        if self.debug {
-            println!("input: {} -> {}\n", self.input, dest);
-        }
-        self.input_ready = false;
+    pub fn image(&self) -> Arc<[i64]> {
+        self.memory.image().clone()
     }
 
-    fn set_output(&mut self, arg: i64) {
-        if self.debug {
-            println!("output: {}\n", arg);
-        }
-        self.output.push_back(arg);
+    pub fn memory(&self) -> Vec<i64> {
+        self.memory.to_vec()
     }
 
-    fn adjust_rb(&mut self, arg: i64) {
-        let tmp = self.rb as i64 + arg;
-        if self.debug {
-            println!("    rb: {}, arg: {}, -> rb: {}\n", self.rb, arg, tmp);
-        }
-        self.rb = tmp as usize;
+    // Memory in the binary format from_path reads.
+    pub fn to_binary(&self) -> Vec<u8> {
+        program::to_binary(&self.memory())
     }
 
-    pub fn debug(&self) {
-        for i in &self.program {
-            println!("{}", i.1);
-        }
+    pub fn disassemble(&self) -> Listing {
+        disassemble(&self.memory())
     }
 
-    pub fn restore(&mut self) {
-        self.program.insert(1, 12);
-        self.program.insert(2, 2);
+    pub fn control_flow(&self) -> Cfg {
+        control_flow(&self.memory())
     }
 
-    pub fn write(&mut self, val: i64, pos: i64) {
-        self.program.insert(pos as usize, val);
+    pub fn decompile(&self) -> String {
+        decompile(&self.memory())
     }
 
-    pub fn read(&mut self, pos: usize) -> i64 {
-        *self.program.entry(pos).or_default()
+    pub fn debug(&self) {
+        for i in self.memory() {
+            println!("{}", i);
+        }
     }
 
-    pub fn set_input(&mut self, data: i64) {
-        self.input = data;
-        self.input_ready = true;
-        self.awaiting_input = false;
+    // Checks every expected old value before writing anything, so a patch
+    // that does not fit leaves memory as it was. Each cell is set with write,
+    // so watchpoints see it.
+    pub fn apply_patch(&mut self, patch: &Patch) -> Result<(), PatchError> {
+        patch.check(|addr| self.memory.get(addr).unwrap_or_default())?;
+        for edit in &patch.edits {
+            for addr in edit.addrs.clone() {
+                self.write(edit.new, addr as i64)
+                    .expect("check rejects addresses past the limit");
+            }
+        }
+        Ok(())
     }
 
-    pub fn get_output(&mut self) -> i64 {
-        self.output.pop_front().unwrap()
+    pub fn patch(&mut self, src: &str) -> Result<(), PatchError> {
+        self.apply_patch(&Patch::parse(src)?)
     }
 
-    pub fn get_last_output(&mut self) -> i64 {
-        self.output.pop_back().unwrap()
+    // Fires watchpoints on pos, but a pause they ask for is ignored.
+    pub fn write(&mut self, val: i64, pos: i64) -> Result<(), ErrorKind> {
+        let pos = address(pos)?;
+        if pos >= MEMORY_LIMIT {
+            return Err(ErrorKind::AddressTooLarge(pos));
+        }
+        if let Some(watch) = &self.watch {
+            watch.notify(&WatchEvent {
+                pc: None,
+                addr: pos,
+                access: Access::Write,
+                old: self.memory.get(pos).unwrap_or_default(),
+                new: val,
+            });
+        }
+        self.memory.set(pos, val)
     }
 
-    pub fn get_outputs(&mut self) -> Vec<i64> {
-        let mut v: Vec<i64> = Vec::new();
-        for n in &self.output {
-            v.push(*n);
+    // Reads past the end of memory see zero. Fires watchpoints like write.
+    pub fn read(&self, pos: usize) -> i64 {
+        let val = self.memory.get(pos).unwrap_or_default();
+        if let Some(watch) = &self.watch {
+            watch.notify(&WatchEvent {
+                pc: None,
+                addr: pos,
+                access: Access::Read,
+                old: val,
+                new: val,
+            });
         }
-        self.output.clear();
-        v
+        val
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn rb(&self) -> i64 {
+        self.rb
     }
 
-    pub fn packet_ready(&self) -> bool {
-        self.output.len() == 3
+    pub fn set_rb(&mut self, rb: i64) {
+        self.rb = rb;
     }
 
     pub fn awaiting_input(&self) -> bool {
@@ -329,27 +642,130 @@ impl Intcode {
         self.halted
     }
 
+    // Instructions executed since the machine was made or last reset.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    // Caps the step count: run and run_until_output stop with
+    // RunState::BudgetExhausted once it is reached. None removes the cap.
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    // Traces every executed instruction to stdout.
     pub fn set_debug(&mut self, b: bool) {
-        self.debug = b;
+        self.trace = b.then(Trace::stdout);
+    }
+
+    pub fn set_trace(&mut self, trace: Trace) {
+        self.trace = Some(trace);
+    }
+
+    pub fn clear_trace(&mut self) {
+        self.trace = None;
+    }
+
+    // Starts counting executions and memory traffic per address. Counts carry
+    // on across reset, so a machine reused for many runs builds one profile.
+    pub fn enable_profiling(&mut self) {
+        self.profile.get_or_insert_with(Box::default);
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_deref()
+    }
+
+    // Stops profiling and returns what was gathered.
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profile.take().map(|p| *p)
+    }
+
+    pub fn profile_report(&self, top: usize) -> Option<String> {
+        let profile = self.profile.as_ref()?;
+        Some(profile.report(&self.memory(), top))
+    }
+
+    // What the profile says was executed and accessed so far.
+    pub fn coverage(&self) -> Option<Coverage> {
+        self.profile.as_deref().map(Coverage::from_profile)
+    }
+
+    // Calls f on each access to addr of the given kind, and returns an id for
+    // unwatch. Watchpoints carry on across reset and fork, and a fork shares
+    // the callbacks.
+    pub fn watch<F>(&mut self, addr: usize, access: Access, f: F) -> usize
+    where
+        F: FnMut(&WatchEvent) -> Action + Send + 'static,
+    {
+        self.watch
+            .get_or_insert_with(Box::default)
+            .add(addr, access, f)
+    }
+
+    pub fn unwatch(&mut self, id: usize) -> bool {
+        self.watch.as_mut().is_some_and(|w| w.remove(id))
+    }
+
+    // Calls f with each value an IN takes, replacing any earlier hook.
+    pub fn on_input<F>(&mut self, f: F)
+    where
+        F: FnMut(&IoEvent) -> Action + Send + 'static,
+    {
+        self.watch
+            .get_or_insert_with(Box::default)
+            .set_input_hook(f);
+    }
+
+    // Calls f with each value an OUT produces, whether it is sent to the
+    // output or handed back by run_until_output.
+    pub fn on_output<F>(&mut self, f: F)
+    where
+        F: FnMut(&IoEvent) -> Action + Send + 'static,
+    {
+        self.watch
+            .get_or_insert_with(Box::default)
+            .set_output_hook(f);
+    }
+
+    // Removes every watchpoint and hook.
+    pub fn clear_watches(&mut self) {
+        self.watch = None;
+    }
+
+    // Whether the last instruction executed asked to pause.
+    pub fn paused(&self) -> bool {
+        self.watch.as_ref().is_some_and(|w| w.paused)
+    }
+
+    // A deep copy of the running machine. Memory pages not yet written are
+    // still shared with the program image.
+    pub fn fork(&self) -> Self
+    where
+        Self: Clone,
+    {
+        self.clone()
     }
 
     pub fn reset(&mut self) {
         self.pc = 0;
         self.rb = 0;
+        self.steps = 0;
         self.halted = false;
-        self.program = parse_program(self.orig_program.clone());
+        self.memory.reset();
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::Intcode as Computer;
+    use super::{ErrorKind, IntcodeError, RunState};
 
     #[test]
     fn simple_add() {
         let s = "1, 0, 0, 0, 99".to_string();
         let mut comp = Computer::intcode_instance(s);
-        comp.run();
+        comp.run().unwrap();
         assert_eq!(comp.read(0), 2);
     }
 
@@ -357,7 +773,7 @@ mod tests {
     fn simple_mul() {
         let s = "2, 3, 0, 3, 99".to_string();
         let mut comp = Computer::intcode_instance(s);
-        comp.run();
+        comp.run().unwrap();
         assert_eq!(comp.read(3), 6);
     }
 
@@ -365,7 +781,7 @@ mod tests {
     fn simple_mul2() {
         let s = "2, 4, 4, 5, 99, 0".to_string();
         let mut comp = Computer::intcode_instance(s);
-        comp.run();
+        comp.run().unwrap();
         assert_eq!(comp.read(5), 9801);
     }
 
@@ -373,7 +789,7 @@ mod tests {
     fn compound() {
         let s = "1, 1, 1, 4, 99, 5, 6, 0, 99".to_string();
         let mut comp = Computer::intcode_instance(s);
-        comp.run();
+        comp.run().unwrap();
         assert!(comp.read(0) == 30 && comp.read(4) == 2);
     }
 
@@ -382,7 +798,7 @@ mod tests {
         let s = "3, 0, 4, 0, 99".to_string();
         let mut comp = Computer::intcode_instance(s);
         comp.set_input(44);
-        comp.run();
+        comp.run().unwrap();
         assert_eq!(comp.get_output(), 44);
     }
 
@@ -390,7 +806,7 @@ mod tests {
     fn mul_modes() {
         let s = "1002, 4, 3, 4, 33".to_string();
         let mut comp = Computer::intcode_instance(s);
-        comp.run();
+        comp.run().unwrap();
         assert_eq!(comp.read(4), 99);
     }
 
@@ -398,7 +814,7 @@ mod tests {
     fn negative() {
         let s = "1101, 100, -1, 4, 0".to_string();
         let mut comp = Computer::intcode_instance(s);
-        comp.run();
+        comp.run().unwrap();
         assert_eq!(comp.read(4), 99);
     }
 
@@ -407,7 +823,7 @@ mod tests {
         let s = "3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8".to_string();
         let mut comp = Computer::intcode_instance(s);
         comp.set_input(8);
-        comp.run();
+        comp.run().unwrap();
         assert_eq!(comp.get_output(), 1);
     }
 
@@ -416,7 +832,7 @@ mod tests {
         let s = "3, 3, 1108, -1, 8, 3, 4, 3, 99".to_string();
         let mut comp = Computer::intcode_instance(s);
         comp.set_input(8);
-        comp.run();
+        comp.run().unwrap();
         assert_eq!(comp.get_output(), 1);
     }
 
@@ -425,7 +841,7 @@ mod tests {
         let s = "3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8".to_string();
         let mut comp = Computer::intcode_instance(s);
         comp.set_input(6);
-        comp.run();
+        comp.run().unwrap();
         assert_eq!(comp.get_output(), 1);
     }
 
@@ -434,7 +850,7 @@ mod tests {
         let s = "3, 3, 1107, -1, 8, 3, 4, 3, 99".to_string();
         let mut comp = Computer::intcode_instance(s);
         comp.set_input(6);
-        comp.run();
+        comp.run().unwrap();
         assert_eq!(comp.get_output(), 1);
     }
 
@@ -443,7 +859,7 @@ mod tests {
         let s = "3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9".to_string();
         let mut comp = Computer::intcode_instance(s);
         comp.set_input(0);
-        comp.run();
+        comp.run().unwrap();
         assert_eq!(comp.get_output(), 0);
     }
 
@@ -452,7 +868,7 @@ mod tests {
         let s = "3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1".to_string();
         let mut comp = Computer::intcode_instance(s);
         comp.set_input(0);
-        comp.run();
+        comp.run().unwrap();
         assert_eq!(comp.get_output(), 0);
     }
 
@@ -460,7 +876,7 @@ mod tests {
     fn test_large_number() {
         let s = "1102,34915192,34915192,7,4,7,99,0".to_string();
         let mut comp = Computer::intcode_instance(s);
-        comp.run();
+        comp.run().unwrap();
         assert_eq!(comp.get_output(), 1219070632396864);
     }
 
@@ -468,7 +884,7 @@ mod tests {
     fn test_copy_program() {
         let s = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99".to_string();
         let mut comp = Computer::intcode_instance(s);
-        comp.run();
+        comp.run().unwrap();
         let result = comp.get_outputs();
         let ans = vec![
             109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
@@ -480,7 +896,193 @@ mod tests {
     fn test_large_output() {
         let s = "104,1125899906842624,99".to_string();
         let mut comp = Computer::intcode_instance(s);
-        comp.run();
+        comp.run().unwrap();
         assert_eq!(comp.get_output(), 1125899906842624);
     }
+
+    #[test]
+    fn invalid_opcode() {
+        let s = "1, 0, 0, 0, 42, 99".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        let err = comp.run().unwrap_err();
+        assert_eq!(
+            err,
+            IntcodeError {
+                pc: 4,
+                instr: 42,
+                kind: ErrorKind::InvalidOpcode
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_mode() {
+        let s = "301, 0, 0, 0, 99".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        assert_eq!(comp.run().unwrap_err().kind, ErrorKind::InvalidMode(3));
+    }
+
+    #[test]
+    fn immediate_write() {
+        let s = "11101, 1, 1, 0, 99".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        assert_eq!(comp.run().unwrap_err().kind, ErrorKind::ImmediateWrite);
+    }
+
+    #[test]
+    fn negative_relative_address() {
+        let s = "109, -5, 204, 1, 99".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        let err = comp.run().unwrap_err();
+        assert_eq!(err.pc, 2);
+        assert_eq!(err.kind, ErrorKind::NegativeAddress(-4));
+    }
+
+    #[test]
+    fn overflow() {
+        let s = format!("1102, {}, 2, 0, 99", i64::MAX);
+        let mut comp = Computer::intcode_instance(s);
+        let err = comp.run().unwrap_err();
+        assert_eq!((err.pc, err.kind), (0, ErrorKind::Overflow));
+        let s = format!("1101, {}, 1, 0, 99", i64::MAX);
+        let mut comp = Computer::intcode_instance(s);
+        assert_eq!(comp.run().unwrap_err().kind, ErrorKind::Overflow);
+        let s = format!("109, {}, 109, 1, 99", i64::MAX);
+        let mut comp = Computer::intcode_instance(s);
+        assert_eq!(comp.run().unwrap_err().kind, ErrorKind::Overflow);
+    }
+
+    #[test]
+    fn huge_and_negative_addresses() {
+        let s = "1101, 1, 1, 1000000000000000, 99".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        let err = comp.run().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::AddressTooLarge(1000000000000000));
+        let s = "99".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        assert_eq!(comp.write(5, -1), Err(ErrorKind::NegativeAddress(-1)));
+        assert_eq!(comp.memory(), vec![99]);
+    }
+
+    #[test]
+    fn read_past_program() {
+        let s = "1, 0, 0, 0".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        assert_eq!(comp.run().unwrap_err().kind, ErrorKind::ReadPastProgram(4));
+    }
+
+    #[test]
+    fn run_states() {
+        let s = "3, 0, 4, 0, 99".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        assert_eq!(comp.run().unwrap(), RunState::NeedsInput);
+        comp.set_input(7);
+        assert_eq!(comp.run().unwrap(), RunState::Halted);
+        assert_eq!(comp.get_output(), 7);
+    }
+
+    #[test]
+    fn run_until_output() {
+        let s = "104, 1, 104, 2, 99".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        assert_eq!(comp.run_until_output().unwrap(), RunState::Output(1));
+        assert_eq!(comp.run_until_output().unwrap(), RunState::Output(2));
+        assert_eq!(comp.run_until_output().unwrap(), RunState::Halted);
+        assert!(comp.get_outputs().is_empty());
+    }
+
+    #[test]
+    fn run_after_halt() {
+        let s = "99".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        assert_eq!(comp.run().unwrap(), RunState::Halted);
+        assert_eq!(comp.run().unwrap(), RunState::Halted);
+        assert_eq!(comp.run_for(5).unwrap(), RunState::Halted);
+        assert_eq!(comp.steps(), 1);
+    }
+
+    #[test]
+    fn run_for() {
+        let s = "1105, 1, 0".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        assert_eq!(comp.run_for(10).unwrap(), RunState::BudgetExhausted(10));
+        assert_eq!(comp.run_for(5).unwrap(), RunState::BudgetExhausted(15));
+        assert_eq!(comp.steps(), 15);
+        let s = "104, 1, 99".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        assert_eq!(comp.run_for(2).unwrap(), RunState::Halted);
+    }
+
+    #[test]
+    fn step_limit() {
+        let s = "3, 5, 1105, 1, 0, 0".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        comp.set_step_limit(Some(5));
+        comp.extend_input([1, 2, 3]);
+        assert_eq!(comp.run().unwrap(), RunState::BudgetExhausted(5));
+        assert_eq!(comp.run_for(100).unwrap(), RunState::BudgetExhausted(5));
+        comp.set_step_limit(None);
+        assert_eq!(comp.run().unwrap(), RunState::NeedsInput);
+        assert_eq!(comp.steps(), 6);
+        comp.reset();
+        assert_eq!(comp.steps(), 0);
+    }
+
+    #[test]
+    fn input_queue() {
+        let s = "3, 0, 3, 1, 1, 0, 1, 2, 4, 2, 99".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        comp.extend_input([3, 4]);
+        assert_eq!(comp.run().unwrap(), RunState::Halted);
+        assert_eq!(comp.get_output(), 7);
+    }
+
+    #[test]
+    fn set_input_overwrites() {
+        let s = "3, 0, 4, 0, 3, 0, 4, 0, 99".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        comp.push_input(1);
+        comp.set_input(2);
+        comp.set_input(3);
+        assert_eq!(comp.run().unwrap(), RunState::NeedsInput);
+        assert_eq!(comp.get_outputs(), vec![3]);
+    }
+
+    #[test]
+    fn memory_grows_on_write() {
+        let s = "1101, 2, 3, 10, 99".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        assert_eq!(comp.read(1000), 0);
+        comp.run().unwrap();
+        assert_eq!(comp.memory(), vec![1101, 2, 3, 10, 99, 0, 0, 0, 0, 0, 5]);
+    }
+
+    #[test]
+    fn shared_image() {
+        let s = "1, 0, 0, 0, 99".to_string();
+        let mut a = Computer::intcode_instance(s);
+        let mut b = Computer::from_image(a.image());
+        a.run().unwrap();
+        assert_eq!(a.read(0), 2);
+        assert_eq!(b.read(0), 1);
+        b.write(4, 1).unwrap();
+        b.run().unwrap();
+        assert_eq!(b.read(0), 100);
+        a.reset();
+        assert_eq!(a.read(0), 1);
+    }
+
+    #[test]
+    fn fork_is_independent() {
+        let s = "3, 20, 1, 20, 21, 21, 4, 21, 1105, 1, 0".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        comp.push_input(1);
+        assert_eq!(comp.run_until_output().unwrap(), RunState::Output(1));
+        let mut fork = comp.fork();
+        fork.push_input(5);
+        assert_eq!(fork.run_until_output().unwrap(), RunState::Output(6));
+        assert_eq!(comp.read(21), 1);
+        comp.push_input(2);
+        assert_eq!(comp.run_until_output().unwrap(), RunState::Output(3));
+    }
 }
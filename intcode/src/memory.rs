@@ -0,0 +1,162 @@
+use crate::error::ErrorKind;
+use std::sync::Arc;
+
+const PAGE: usize = 64;
+
+// Writes at or past this address fail rather than growing the page table
+// without bound.
+pub(crate) const MEMORY_LIMIT: usize = 1 << 26;
+
+// Machine memory: a shared, immutable program image plus the pages this
+// machine has written to. Writing to a page copies it out of the image first,
+// so new machines and resets never copy the whole program.
+#[derive(Debug, Clone)]
+pub struct Memory {
+    image: Arc<[i64]>,
+    pages: Vec<Option<Box<[i64; PAGE]>>>,
+    len: usize,
+}
+
+impl Memory {
+    pub fn new(image: Arc<[i64]>) -> Self {
+        Self {
+            len: image.len(),
+            image,
+            pages: Vec::new(),
+        }
+    }
+
+    pub fn image(&self) -> &Arc<[i64]> {
+        &self.image
+    }
+
+    // None past the end of memory.
+    pub fn get(&self, pos: usize) -> Option<i64> {
+        if pos >= self.len {
+            return None;
+        }
+        match self.pages.get(pos / PAGE) {
+            Some(Some(page)) => Some(page[pos % PAGE]),
+            _ => Some(self.image.get(pos).copied().unwrap_or_default()),
+        }
+    }
+
+    // Writes past the end grow memory with zeroes.
+    pub fn set(&mut self, pos: usize, val: i64) -> Result<(), ErrorKind> {
+        if pos >= MEMORY_LIMIT {
+            return Err(ErrorKind::AddressTooLarge(pos));
+        }
+        self.put(pos, val);
+        Ok(())
+    }
+
+    fn put(&mut self, pos: usize, val: i64) {
+        let n = pos / PAGE;
+        if n >= self.pages.len() {
+            self.pages.resize_with(n + 1, || None);
+        }
+        let image = &self.image;
+        let page = self.pages[n].get_or_insert_with(|| {
+            let mut page = Box::new([0; PAGE]);
+            let start = (n * PAGE).min(image.len());
+            let end = ((n + 1) * PAGE).min(image.len());
+            page[..end - start].copy_from_slice(&image[start..end]);
+            page
+        });
+        page[pos % PAGE] = val;
+        self.len = self.len.max(pos + 1);
+    }
+
+    // Drops every write, going back to the image.
+    pub fn reset(&mut self) {
+        self.pages.clear();
+        self.len = self.image.len();
+    }
+
+    // Makes memory hold exactly values, keeping the image to share. Image
+    // cells past the end of values are zeroed, so growing memory back over
+    // them later finds zeroes rather than the program.
+    pub fn load(&mut self, values: &[i64]) {
+        self.reset();
+        for (i, v) in values.iter().enumerate() {
+            if self.image.get(i) != Some(v) {
+                self.put(i, *v);
+            }
+        }
+        for i in values.len()..self.image.len() {
+            if self.image[i] != 0 {
+                self.put(i, 0);
+            }
+        }
+        self.len = values.len();
+    }
+
+    pub fn to_vec(&self) -> Vec<i64> {
+        (0..self.len).map(|i| self.get(i).unwrap()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_on_write() {
+        let image: Arc<[i64]> = (0..100).collect();
+        let mut a = Memory::new(image.clone());
+        let b = Memory::new(image.clone());
+        a.set(70, -1).unwrap();
+        assert_eq!(a.get(70), Some(-1));
+        assert_eq!(a.get(71), Some(71));
+        assert_eq!(b.get(70), Some(70));
+        assert_eq!(image[70], 70);
+        a.reset();
+        assert_eq!(a.get(70), Some(70));
+    }
+
+    #[test]
+    fn load_keeps_image() {
+        let image: Arc<[i64]> = (0..100).collect();
+        let mut m = Memory::new(image.clone());
+        m.load(&[5; 130]);
+        assert_eq!(m.get(0), Some(5));
+        assert_eq!(m.get(129), Some(5));
+        assert_eq!(m.get(130), None);
+        m.reset();
+        assert_eq!(m.to_vec(), image.to_vec());
+    }
+
+    #[test]
+    fn load_shorter_than_image() {
+        let image: Arc<[i64]> = (1..=100).collect();
+        let mut m = Memory::new(image.clone());
+        m.load(&[7, 8, 9]);
+        assert_eq!(m.get(3), None);
+        m.set(80, -1).unwrap();
+        assert_eq!(m.to_vec()[..4], [7, 8, 9, 0]);
+        assert!(m.to_vec()[3..80].iter().all(|v| *v == 0));
+        assert_eq!(m.get(80), Some(-1));
+        m.reset();
+        assert_eq!(m.to_vec(), image.to_vec());
+    }
+
+    #[test]
+    fn refuses_huge_addresses() {
+        let mut m = Memory::new(Arc::from(vec![1, 2, 3]));
+        let err = m.set(1 << 50, 1).unwrap_err();
+        assert_eq!(err, ErrorKind::AddressTooLarge(1 << 50));
+        assert_eq!(m.get(1 << 50), None);
+        assert_eq!(m.to_vec(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn grows_past_image() {
+        let mut m = Memory::new(Arc::from(vec![1, 2, 3]));
+        assert_eq!(m.get(3), None);
+        m.set(200, 9).unwrap();
+        assert_eq!(m.to_vec().len(), 201);
+        assert_eq!(m.get(150), Some(0));
+        assert_eq!(m.get(200), Some(9));
+        assert_eq!(m.to_vec()[..4], [1, 2, 3, 0]);
+    }
+}
@@ -0,0 +1,278 @@
+use crate::error::IntcodeError;
+use crate::Intcode;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub from: usize,
+    pub to: i64,
+    pub values: Vec<i64>,
+}
+
+// What sits at the NAT address: it takes the packets sent there, and gets a
+// chance to wake the network up when it goes idle.
+pub trait Nat {
+    // Returning false stops the network.
+    fn receive(&mut self, packet: &Packet) -> bool;
+
+    // Called when the network is idle. The packet returned is delivered, and
+    // None stops the network.
+    fn wake(&mut self) -> Option<Packet>;
+}
+
+// A NAT that stops the network at the first packet it gets.
+#[derive(Debug, Clone, Default)]
+pub struct FirstPacket {
+    pub packet: Option<Packet>,
+}
+
+impl Nat for FirstPacket {
+    fn receive(&mut self, packet: &Packet) -> bool {
+        self.packet = Some(packet.clone());
+        false
+    }
+
+    fn wake(&mut self) -> Option<Packet> {
+        None
+    }
+}
+
+struct Node {
+    comp: Intcode,
+    queued: usize,
+    partial: Vec<i64>,
+}
+
+// Machines that send each other packets of packet_size values: an address
+// followed by a payload. Each machine first reads its own address. A machine
+// with nothing queued reads the no-input value instead. Runs one machine at a
+// time, so a run is deterministic.
+pub struct Network {
+    nodes: Vec<Node>,
+    packet_size: usize,
+    nat_address: i64,
+    no_input: i64,
+    log: Vec<Packet>,
+}
+
+impl Network {
+    pub fn new(image: Arc<[i64]>, nodes: usize) -> Self {
+        let nodes = (0..nodes)
+            .map(|addr| {
+                let mut comp = Intcode::from_image(image.clone());
+                comp.push_input(addr as i64);
+                Node {
+                    comp,
+                    queued: 1,
+                    partial: Vec::new(),
+                }
+            })
+            .collect();
+        Self {
+            nodes,
+            packet_size: 3,
+            nat_address: 255,
+            no_input: -1,
+            log: Vec::new(),
+        }
+    }
+
+    // Panics on a size of 0, as every packet holds at least its address.
+    pub fn set_packet_size(&mut self, size: usize) {
+        assert!(size > 0, "packets need room for an address");
+        self.packet_size = size;
+    }
+
+    pub fn set_nat_address(&mut self, addr: i64) {
+        self.nat_address = addr;
+    }
+
+    pub fn set_no_input(&mut self, value: i64) {
+        self.no_input = value;
+    }
+
+    // Every packet sent so far, in order, including those to and from the NAT
+    // and those to addresses nobody has.
+    pub fn log(&self) -> &[Packet] {
+        &self.log
+    }
+
+    // Values waiting to be read by a machine.
+    pub fn queue(&self, addr: usize) -> Vec<i64> {
+        self.nodes[addr].comp.peek_inputs()
+    }
+
+    pub fn node(&self, addr: usize) -> &Intcode {
+        &self.nodes[addr].comp
+    }
+
+    fn deliver(&mut self, packet: Packet) {
+        if let Some(node) = usize::try_from(packet.to)
+            .ok()
+            .and_then(|a| self.nodes.get_mut(a))
+        {
+            node.comp.extend_input(packet.values.iter().copied());
+            node.queued += packet.values.len();
+        }
+        self.log.push(packet);
+    }
+
+    // Runs each machine in turn until it needs input, routing the packets it
+    // sent. The network is idle after a round in which every machine was
+    // starved: it had nothing queued, read only the no-input value and sent
+    // nothing. Runs until the NAT stops it or every machine halts.
+    pub fn run<N: Nat>(&mut self, nat: &mut N) -> Result<(), IntcodeError> {
+        loop {
+            let mut idle = true;
+            for addr in 0..self.nodes.len() {
+                let node = &mut self.nodes[addr];
+                if node.comp.is_halted() {
+                    continue;
+                }
+                let starved = node.queued == 0;
+                if starved {
+                    node.comp.push_input(self.no_input);
+                }
+                node.queued = 0;
+                node.comp.run()?;
+                let output = node.comp.get_outputs();
+                idle &= starved && output.is_empty() && node.partial.is_empty();
+
+                let mut packets = Vec::new();
+                for v in output {
+                    node.partial.push(v);
+                    if node.partial.len() == self.packet_size {
+                        let mut values = std::mem::take(&mut node.partial);
+                        let to = values.remove(0);
+                        packets.push(Packet {
+                            from: addr,
+                            to,
+                            values,
+                        });
+                    }
+                }
+                for packet in packets {
+                    if packet.to == self.nat_address {
+                        self.log.push(packet.clone());
+                        if !nat.receive(&packet) {
+                            return Ok(());
+                        }
+                    } else {
+                        self.deliver(packet);
+                    }
+                }
+            }
+
+            if self.nodes.iter().all(|n| n.comp.is_halted()) {
+                return Ok(());
+            }
+            if idle && self.nodes.iter().all(|n| n.queued == 0) {
+                match nat.wake() {
+                    Some(packet) => self.deliver(packet),
+                    None => return Ok(()),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble;
+
+    // Node 0 sends (1, 10, 20) once. Every node passes each x, y it gets on
+    // to the next address, and the last node sends them to 255.
+    const RELAY: &str = "
+                IN [addr]
+                JT [addr], #loop
+                OUT #1
+                OUT #10
+                OUT #20
+        loop:   IN [x]
+                EQ [x], #-1, [t]
+                JT [t], #loop
+                IN [y]
+                ADD [addr], #1, [to]
+                EQ [to], #3, [t]
+                JF [t], #send
+                ADD #255, #0, [to]
+        send:   OUT [to]
+                OUT [x]
+                OUT [y]
+                JT #1, #loop
+        addr:   .data 0
+        x:      .data 0
+        y:      .data 0
+        to:     .data 0
+        t:      .data 0
+    ";
+
+    fn relay() -> Network {
+        let program = assemble(RELAY).unwrap();
+        Network::new(Intcode::intcode_instance(program).image(), 3)
+    }
+
+    #[test]
+    fn routes_to_the_nat() {
+        let mut net = relay();
+        let mut nat = FirstPacket::default();
+        net.run(&mut nat).unwrap();
+        let packet = nat.packet.unwrap();
+        assert_eq!(
+            packet,
+            Packet {
+                from: 2,
+                to: 255,
+                values: vec![10, 20]
+            }
+        );
+        let route: Vec<_> = net.log().iter().map(|p| (p.from, p.to)).collect();
+        assert_eq!(route, vec![(0, 1), (1, 2), (2, 255)]);
+    }
+
+    // Sends the last packet it got to node 0 when the network goes idle, until
+    // it has done so twice.
+    struct Twice {
+        last: Option<Packet>,
+        woken: usize,
+    }
+
+    impl Nat for Twice {
+        fn receive(&mut self, packet: &Packet) -> bool {
+            self.last = Some(packet.clone());
+            true
+        }
+
+        fn wake(&mut self) -> Option<Packet> {
+            self.woken += 1;
+            let last = self.last.as_ref()?;
+            (self.woken <= 2).then(|| Packet {
+                from: 255,
+                to: 0,
+                values: last.values.clone(),
+            })
+        }
+    }
+
+    #[test]
+    fn wakes_when_idle() {
+        let mut net = relay();
+        let mut nat = Twice {
+            last: None,
+            woken: 0,
+        };
+        net.run(&mut nat).unwrap();
+        assert_eq!(nat.woken, 3);
+        let to_nat = net.log().iter().filter(|p| p.to == 255).count();
+        assert_eq!(to_nat, 3);
+        assert_eq!(net.log().len(), 11);
+        assert!(net.queue(0).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "packets need room for an address")]
+    fn empty_packets() {
+        relay().set_packet_size(0);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::memory::MEMORY_LIMIT;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchErrorKind {
+    Syntax(String),
+    BadNumber(String),
+    EmptyRange(Range<usize>),
+    AddressTooLarge(usize),
+    Mismatch {
+        addr: usize,
+        expected: i64,
+        found: i64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchError {
+    pub line: usize,
+    pub kind: PatchErrorKind,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            PatchErrorKind::Syntax(s) => write!(f, "expected ADDR = [OLD ->] NEW, found {}", s),
+            PatchErrorKind::BadNumber(n) => write!(f, "bad number {}", n),
+            PatchErrorKind::EmptyRange(r) => write!(f, "empty range {}..{}", r.start, r.end),
+            PatchErrorKind::AddressTooLarge(a) => {
+                write!(f, "address {} is past the memory limit", a)
+            }
+            PatchErrorKind::Mismatch {
+                addr,
+                expected,
+                found,
+            } => write!(f, "address {} holds {}, expected {}", addr, found, expected),
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+// Sets every cell in addrs to new. With old, each cell must hold it first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub line: usize,
+    pub addrs: Range<usize>,
+    pub old: Option<i64>,
+    pub new: i64,
+}
+
+// A list of edits, one per line:
+//
+//   # comment
+//   1 = 12              set address 1
+//   0 = 1 -> 2          set address 0, which must hold 1
+//   1288..1322 = 0 -> 1 fill a half-open range
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Patch {
+    pub edits: Vec<Edit>,
+}
+
+fn number<T: std::str::FromStr>(s: &str) -> Result<T, PatchErrorKind> {
+    s.trim()
+        .parse()
+        .map_err(|_| PatchErrorKind::BadNumber(s.trim().to_string()))
+}
+
+fn parse_edit(line: usize, text: &str) -> Result<Option<Edit>, PatchErrorKind> {
+    if text.is_empty() {
+        return Ok(None);
+    }
+    let (addrs, value) = text
+        .split_once('=')
+        .ok_or_else(|| PatchErrorKind::Syntax(text.to_string()))?;
+    let addrs = match addrs.split_once("..") {
+        Some((start, end)) => number(start)?..number(end)?,
+        None => {
+            let addr = number(addrs)?;
+            addr..addr + 1
+        }
+    };
+    if addrs.is_empty() {
+        return Err(PatchErrorKind::EmptyRange(addrs));
+    }
+    let (old, new) = match value.split_once("->") {
+        Some((old, new)) => (Some(number(old)?), number(new)?),
+        None => (None, number(value)?),
+    };
+    Ok(Some(Edit {
+        line,
+        addrs,
+        old,
+        new,
+    }))
+}
+
+impl Patch {
+    pub fn parse(src: &str) -> Result<Patch, PatchError> {
+        let mut edits = Vec::new();
+        for (i, line) in src.lines().enumerate() {
+            let text = line.split('#').next().unwrap().trim();
+            let edit = parse_edit(i + 1, text).map_err(|kind| PatchError { line: i + 1, kind })?;
+            edits.extend(edit);
+        }
+        Ok(Patch { edits })
+    }
+
+    // The first edit reaching past the memory limit, or the first cell that
+    // does not hold what its edit expects, reading cells through get.
+    pub(crate) fn check(&self, get: impl Fn(usize) -> i64) -> Result<(), PatchError> {
+        for edit in &self.edits {
+            if edit.addrs.end > MEMORY_LIMIT {
+                return Err(PatchError {
+                    line: edit.line,
+                    kind: PatchErrorKind::AddressTooLarge(edit.addrs.end - 1),
+                });
+            }
+            let Some(expected) = edit.old else {
+                continue;
+            };
+            for addr in edit.addrs.clone() {
+                let found = get(addr);
+                if found != expected {
+                    return Err(PatchError {
+                        line: edit.line,
+                        kind: PatchErrorKind::Mismatch {
+                            addr,
+                            expected,
+                            found,
+                        },
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Access, Action, Intcode};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn parse_and_apply() {
+        let patch = Patch::parse("# wake up\n0 = 1 -> 2\n\n2..5 = 7  # fill\n").unwrap();
+        assert_eq!(patch.edits.len(), 2);
+        assert_eq!(patch.edits[1].line, 4);
+        let mut comp = Intcode::intcode_instance("1, 0, 0, 0, 99".to_string());
+        comp.apply_patch(&patch).unwrap();
+        assert_eq!(comp.memory(), vec![2, 0, 7, 7, 7]);
+    }
+
+    #[test]
+    fn mismatch_leaves_memory_alone() {
+        let mut comp = Intcode::intcode_instance("1, 0, 0, 0, 99".to_string());
+        let err = comp.patch("0 = 5\n3..5 = 0 -> 1").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(
+            err.kind,
+            PatchErrorKind::Mismatch {
+                addr: 4,
+                expected: 0,
+                found: 99
+            }
+        );
+        assert_eq!(comp.memory(), vec![1, 0, 0, 0, 99]);
+        let err = comp.patch("0 = 2\n100000000000 = 1").unwrap_err();
+        assert_eq!(err.kind, PatchErrorKind::AddressTooLarge(100000000000));
+        assert_eq!(comp.memory(), vec![1, 0, 0, 0, 99]);
+    }
+
+    #[test]
+    fn watchpoints_see_patched_cells() {
+        let mut comp = Intcode::intcode_instance("1, 0, 0, 0, 99".to_string());
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let events = seen.clone();
+        comp.watch(3, Access::Write, move |e| {
+            events.lock().unwrap().push((e.pc, e.old, e.new));
+            Action::Continue
+        });
+        comp.patch("2..4 = 0 -> 6").unwrap();
+        assert_eq!(*seen.lock().unwrap(), vec![(None, 0, 6)]);
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let line = |src| Patch::parse(src).unwrap_err().line;
+        assert_eq!(line("1 = 2\n3 4"), 2);
+        assert_eq!(line("1 = x"), 1);
+        assert_eq!(line("\n5..5 = 1"), 2);
+        assert_eq!(line("-1 = 0"), 1);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::error::IntcodeError;
+use crate::Intcode;
+use itertools::Itertools;
+use rayon::prelude::*;
+use std::sync::Arc;
+
+// Machines in a row, each stage's output feeding the next stage's input. In
+// feedback mode the last stage feeds the first.
+pub struct Pipeline {
+    stages: Vec<Intcode>,
+    feedback: bool,
+}
+
+impl Pipeline {
+    // Stages keep whatever input they already have queued, which they read
+    // before any signal.
+    pub fn new(stages: Vec<Intcode>) -> Self {
+        Self {
+            stages,
+            feedback: false,
+        }
+    }
+
+    // A copy of the program per stage, each first reading its own initial
+    // inputs.
+    pub fn copies(image: &Arc<[i64]>, inputs: &[Vec<i64>]) -> Self {
+        let stages = inputs
+            .iter()
+            .map(|input| {
+                let mut comp = Intcode::from_image(image.clone());
+                comp.extend_input(input.iter().copied());
+                comp
+            })
+            .collect();
+        Self::new(stages)
+    }
+
+    // The day07 amplifiers: a copy per phase, each reading its phase first.
+    pub fn with_phases(image: &Arc<[i64]>, phases: &[i64]) -> Self {
+        let inputs: Vec<_> = phases.iter().map(|p| vec![*p]).collect();
+        Self::copies(image, &inputs)
+    }
+
+    pub fn set_feedback(&mut self, feedback: bool) {
+        self.feedback = feedback;
+    }
+
+    pub fn stage(&self, i: usize) -> &Intcode {
+        &self.stages[i]
+    }
+
+    // Sends signal to the first stage and runs the stages in turn, passing
+    // outputs along, until the last stage halts or nothing moves any more.
+    // Returns the last value the last stage sent, if any.
+    pub fn run(&mut self, signal: i64) -> Result<Option<i64>, IntcodeError> {
+        let mut carry = vec![signal];
+        let mut last = None;
+        loop {
+            let mut moved = false;
+            for stage in self.stages.iter_mut() {
+                moved |= !carry.is_empty();
+                stage.extend_input(carry.drain(..));
+                stage.run()?;
+                carry = stage.get_outputs();
+            }
+            if let Some(v) = carry.last() {
+                last = Some(*v);
+            }
+            let done = self.stages.last().is_none_or(|s| s.is_halted());
+            if !self.feedback || done || !moved && carry.is_empty() {
+                return Ok(last);
+            }
+        }
+    }
+}
+
+// Tries every order of phases, in parallel, and returns the highest final
+// signal from an input of 0 together with the phases that gave it.
+pub fn phase_search(
+    image: &Arc<[i64]>,
+    phases: &[i64],
+    feedback: bool,
+) -> Result<Option<(i64, Vec<i64>)>, IntcodeError> {
+    let orders: Vec<Vec<i64>> = phases.iter().copied().permutations(phases.len()).collect();
+    let results = orders
+        .into_par_iter()
+        .map(|order| {
+            let mut pipeline = Pipeline::with_phases(image, &order);
+            pipeline.set_feedback(feedback);
+            Ok(pipeline.run(0)?.map(|signal| (signal, order)))
+        })
+        .collect::<Result<Vec<_>, IntcodeError>>()?;
+    Ok(results
+        .into_iter()
+        .flatten()
+        .max_by_key(|(signal, _)| *signal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(program: &str) -> Arc<[i64]> {
+        Intcode::intcode_instance(program.to_string()).image()
+    }
+
+    #[test]
+    fn linear() {
+        let image = image("3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0");
+        let mut pipeline = Pipeline::with_phases(&image, &[4, 3, 2, 1, 0]);
+        assert_eq!(pipeline.run(0).unwrap(), Some(43210));
+        assert!(pipeline.stage(4).is_halted());
+    }
+
+    #[test]
+    fn feedback() {
+        let image = image(
+            "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5",
+        );
+        let mut pipeline = Pipeline::with_phases(&image, &[9, 8, 7, 6, 5]);
+        pipeline.set_feedback(true);
+        assert_eq!(pipeline.run(0).unwrap(), Some(139629729));
+    }
+
+    #[test]
+    fn search() {
+        let image = image("3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0");
+        let best = phase_search(&image, &[0, 1, 2, 3, 4], false).unwrap();
+        assert_eq!(best, Some((43210, vec![4, 3, 2, 1, 0])));
+    }
+
+    #[test]
+    fn stops_when_stuck() {
+        // Both stages wait for a second input that never comes.
+        let image = image("3, 20, 3, 21, 99");
+        let mut pipeline = Pipeline::copies(&image, &[vec![], vec![]]);
+        pipeline.set_feedback(true);
+        assert_eq!(pipeline.run(1).unwrap(), None);
+    }
+}
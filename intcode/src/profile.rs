@@ -0,0 +1,168 @@
+use crate::instruction::{decode, Opcode};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+// Execution counts gathered while profiling is on. Addresses index the
+// vectors, which grow as higher addresses are touched.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub executed: Vec<u64>,
+    pub reads: Vec<u64>,
+    pub writes: Vec<u64>,
+    pub opcodes: HashMap<Opcode, u64>,
+}
+
+fn bump(counts: &mut Vec<u64>, addr: usize) {
+    if addr >= counts.len() {
+        counts.resize(addr + 1, 0);
+    }
+    counts[addr] += 1;
+}
+
+fn count(counts: &[u64], addr: usize) -> u64 {
+    counts.get(addr).copied().unwrap_or_default()
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The counters are cold so that machines without a profile pay only for
+    // the check.
+    #[cold]
+    pub(crate) fn execute(&mut self, addr: usize, op: Opcode) {
+        bump(&mut self.executed, addr);
+        *self.opcodes.entry(op).or_default() += 1;
+    }
+
+    #[cold]
+    pub(crate) fn read(&mut self, addr: usize) {
+        bump(&mut self.reads, addr);
+    }
+
+    #[cold]
+    pub(crate) fn write(&mut self, addr: usize) {
+        bump(&mut self.writes, addr);
+    }
+
+    pub fn total(&self) -> u64 {
+        self.executed.iter().sum()
+    }
+
+    // Adds other's counts to these, e.g. to combine the machines of a network.
+    pub fn merge(&mut self, other: &Profile) {
+        for (mine, theirs) in [
+            (&mut self.executed, &other.executed),
+            (&mut self.reads, &other.reads),
+            (&mut self.writes, &other.writes),
+        ] {
+            if theirs.len() > mine.len() {
+                mine.resize(theirs.len(), 0);
+            }
+            for (m, t) in mine.iter_mut().zip(theirs) {
+                *m += t;
+            }
+        }
+        for (op, n) in &other.opcodes {
+            *self.opcodes.entry(*op).or_default() += n;
+        }
+    }
+
+    // The top addresses by execution count, hottest first, ties by address.
+    pub fn hot_spots(&self, top: usize) -> Vec<(usize, u64)> {
+        let mut spots: Vec<_> = (self.executed.iter().copied().enumerate())
+            .filter(|(_, n)| *n > 0)
+            .collect();
+        spots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        spots.truncate(top);
+        spots
+    }
+
+    // A report of the top addresses by executions and by memory traffic, with
+    // the instruction at each hot address decoded from mem.
+    pub fn report(&self, mem: &[i64], top: usize) -> String {
+        let total = self.total().max(1) as f64;
+        let mut out = String::new();
+        writeln!(out, "{} instructions executed", self.total()).unwrap();
+
+        let mut opcodes: Vec<_> = self.opcodes.iter().collect();
+        opcodes.sort_by(|a, b| b.1.cmp(a.1).then(a.0.code().cmp(&b.0.code())));
+        writeln!(out, "\nby opcode:").unwrap();
+        for (op, n) in opcodes {
+            let pct = *n as f64 * 100.0 / total;
+            writeln!(out, "  {:<4} {:>12} {:>6.2}%", op.mnemonic(), n, pct).unwrap();
+        }
+
+        writeln!(out, "\nhot spots:").unwrap();
+        for (addr, n) in self.hot_spots(top) {
+            let instr = match decode(mem, addr) {
+                Ok(i) => i.to_string(),
+                Err(_) => format!(".data {}", mem.get(addr).copied().unwrap_or_default()),
+            };
+            let pct = n as f64 * 100.0 / total;
+            writeln!(out, "  {:>12} {:>6.2}% {:>5}: {}", n, pct, addr, instr).unwrap();
+        }
+
+        let len = self.reads.len().max(self.writes.len());
+        let mut traffic: Vec<_> = (0..len)
+            .map(|a| (a, count(&self.reads, a), count(&self.writes, a)))
+            .filter(|(_, r, w)| r + w > 0)
+            .collect();
+        traffic.sort_by(|a, b| (b.1 + b.2).cmp(&(a.1 + a.2)).then(a.0.cmp(&b.0)));
+        traffic.truncate(top);
+        writeln!(out, "\nmemory:").unwrap();
+        writeln!(out, "  {:>5} {:>12} {:>12}", "addr", "reads", "writes").unwrap();
+        for (addr, r, w) in traffic {
+            writeln!(out, "  {:>5} {:>12} {:>12}", addr, r, w).unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Intcode as Computer;
+
+    #[test]
+    fn counts() {
+        let s = "4, 10, 1001, 10, -1, 10, 1005, 10, 0, 99, 3".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        comp.enable_profiling();
+        comp.run().unwrap();
+        let profile = comp.profile().unwrap();
+        assert_eq!(profile.total(), 10);
+        assert_eq!(profile.hot_spots(4), vec![(0, 3), (2, 3), (6, 3), (9, 1)]);
+        assert_eq!(profile.reads[10], 9);
+        assert_eq!(profile.writes[10], 3);
+        assert_eq!(profile.opcodes[&crate::Opcode::Hlt], 1);
+    }
+
+    #[test]
+    fn merge() {
+        let s = "4, 10, 1001, 10, -1, 10, 1005, 10, 0, 99, 3".to_string();
+        let mut a = Computer::intcode_instance(s);
+        a.enable_profiling();
+        let mut b = a.clone();
+        a.run().unwrap();
+        b.run().unwrap();
+        let mut profile = a.take_profile().unwrap();
+        profile.merge(b.profile().unwrap());
+        assert_eq!(profile.total(), 20);
+        assert_eq!(profile.writes[10], 6);
+        assert!(a.profile().is_none());
+    }
+
+    #[test]
+    fn report() {
+        let s = "4, 10, 1001, 10, -1, 10, 1005, 10, 0, 99, 3".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        comp.enable_profiling();
+        comp.run().unwrap();
+        let report = comp.profile_report(2).unwrap();
+        assert!(report.starts_with("10 instructions executed\n"));
+        assert!(report.contains("  ADD             3  30.00%\n"));
+        assert!(report.contains("             3  30.00%     0: OUT [10]\n"));
+        assert!(report.contains("     10            9            3\n"));
+    }
+}
@@ -0,0 +1,191 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+// Binary programs start with this, then hold each value as 8 little-endian
+// bytes. A text program can never start with a NUL.
+pub const BINARY_MAGIC: [u8; 4] = *b"\0ICB";
+
+#[derive(Debug)]
+pub enum ProgramError {
+    Io(io::Error),
+    BadNumber {
+        line: usize,
+        column: usize,
+        text: String,
+    },
+    EmptyField {
+        line: usize,
+        column: usize,
+    },
+    // A binary program whose values are not a whole number of 8 byte words.
+    Truncated(usize),
+    NotText,
+}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgramError::Io(e) => write!(f, "{}", e),
+            ProgramError::BadNumber { line, column, text } => {
+                write!(f, "line {}, column {}: bad number {}", line, column, text)
+            }
+            ProgramError::EmptyField { line, column } => {
+                write!(f, "line {}, column {}: empty field", line, column)
+            }
+            ProgramError::Truncated(len) => {
+                write!(f, "binary program of {} bytes ends mid-value", len)
+            }
+            ProgramError::NotText => write!(f, "neither a text nor a binary program"),
+        }
+    }
+}
+
+impl Error for ProgramError {}
+
+impl From<io::Error> for ProgramError {
+    fn from(e: io::Error) -> Self {
+        ProgramError::Io(e)
+    }
+}
+
+// Values are separated by commas, whitespace or both, and # starts a comment
+// running to the end of the line. A comma may end the program, but two commas
+// with no value between them are an empty field.
+pub(crate) fn parse_text(src: &str) -> Result<Vec<i64>, ProgramError> {
+    let mut values = Vec::new();
+    // Whether a value has come since the last comma.
+    let mut field = false;
+    for (i, line) in src.lines().enumerate() {
+        let line_no = i + 1;
+        let text = line.split('#').next().unwrap();
+        let column = |start: usize| text[..start].chars().count() + 1;
+        let mut chars = text.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            if c.is_whitespace() {
+                continue;
+            }
+            if c == ',' {
+                if !field {
+                    return Err(ProgramError::EmptyField {
+                        line: line_no,
+                        column: column(start),
+                    });
+                }
+                field = false;
+                continue;
+            }
+            let mut end = start + c.len_utf8();
+            while let Some(&(i, c)) = chars.peek() {
+                if c == ',' || c.is_whitespace() {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let word = &text[start..end];
+            let value = word.parse().map_err(|_| ProgramError::BadNumber {
+                line: line_no,
+                column: column(start),
+                text: word.to_string(),
+            })?;
+            values.push(value);
+            field = true;
+        }
+    }
+    Ok(values)
+}
+
+pub(crate) fn parse_binary(bytes: &[u8]) -> Result<Vec<i64>, ProgramError> {
+    let words = &bytes[BINARY_MAGIC.len()..];
+    if !words.len().is_multiple_of(8) {
+        return Err(ProgramError::Truncated(bytes.len()));
+    }
+    let words = words.chunks_exact(8);
+    Ok(words
+        .map(|w| i64::from_le_bytes(w.try_into().unwrap()))
+        .collect())
+}
+
+// Binary if it starts with the magic, text otherwise.
+pub(crate) fn parse_bytes(bytes: &[u8]) -> Result<Vec<i64>, ProgramError> {
+    if bytes.starts_with(&BINARY_MAGIC) {
+        return parse_binary(bytes);
+    }
+    parse_text(std::str::from_utf8(bytes).map_err(|_| ProgramError::NotText)?)
+}
+
+pub(crate) fn to_binary(values: &[i64]) -> Vec<u8> {
+    let mut bytes = BINARY_MAGIC.to_vec();
+    for v in values {
+        bytes.extend_from_slice(&v.to_le_bytes());
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Intcode;
+    use std::{env, fs, process};
+
+    fn location(src: &str) -> (usize, usize) {
+        match parse_text(src).unwrap_err() {
+            ProgramError::BadNumber { line, column, .. } => (line, column),
+            ProgramError::EmptyField { line, column } => (line, column),
+            e => panic!("unexpected {}", e),
+        }
+    }
+
+    #[test]
+    fn separators_comments_and_trailing_commas() {
+        let src = "# header\n1,2, 3\n4 5\t-6,  # six\n\n7,\n";
+        assert_eq!(parse_text(src).unwrap(), vec![1, 2, 3, 4, 5, -6, 7]);
+        assert_eq!(parse_text("1,2,3,4\n").unwrap(), vec![1, 2, 3, 4]);
+        assert!(parse_text("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn errors_say_where() {
+        assert_eq!(location("1,2\n3,x4,5"), (2, 3));
+        assert_eq!(location("1,,2"), (1, 3));
+        assert_eq!(location("1,\n  ,2"), (2, 3));
+        assert_eq!(location(",1"), (1, 1));
+        assert_eq!(location("1, 2;3"), (1, 4));
+    }
+
+    #[test]
+    fn from_path_reads_both_formats() {
+        let comp: Intcode = "1, 0, 0, 0, 99,\n".parse().unwrap();
+        let name = format!("intcode_program_test_{}.icb", process::id());
+        let path = env::temp_dir().join(name);
+        fs::write(&path, comp.to_binary()).unwrap();
+        let copy = Intcode::from_path(&path);
+        fs::write(&path, "1 0 0 0 # add\n99").unwrap();
+        let text = Intcode::from_path(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(copy.unwrap().memory(), comp.memory());
+        assert_eq!(text.unwrap().memory(), comp.memory());
+        assert!(matches!(
+            Intcode::from_path(&path),
+            Err(ProgramError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let values = vec![1, -2, i64::MAX, 99];
+        let bytes = to_binary(&values);
+        assert_eq!(bytes.len(), 4 + 8 * 4);
+        assert_eq!(parse_bytes(&bytes).unwrap(), values);
+        assert_eq!(parse_bytes(b"1, 2").unwrap(), vec![1, 2]);
+        assert!(matches!(
+            parse_bytes(&bytes[..bytes.len() - 1]),
+            Err(ProgramError::Truncated(_))
+        ));
+        assert!(matches!(
+            parse_bytes(&[0xff, 0xfe]),
+            Err(ProgramError::NotText)
+        ));
+    }
+}
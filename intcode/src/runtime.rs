@@ -0,0 +1,486 @@
+use crate::error::IntcodeError;
+use crate::io::{InputSource, OutputSink};
+use crate::Intcode;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// What a machine does when it wants input and its channel is empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoInput {
+    // Wait for a value to arrive.
+    Block,
+    // Read this value instead, like day23's -1.
+    Value(i64),
+}
+
+// Where a machine's output goes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Route {
+    // Nowhere: the values are only kept in the outcome.
+    Collect,
+    // Every value to one machine, for pipelines and rings.
+    To(usize),
+    // Every value to each of these machines, e.g. from the hub of a star.
+    All(Vec<usize>),
+    // Packets of size values, the first being the index of the machine that
+    // gets the rest. Packets for other addresses end up undelivered.
+    Packets(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    // Every machine halted.
+    Halted,
+    // Every machine is halted or blocked on an empty channel.
+    Deadlock,
+    // Every machine is halted or only reading the no-input value, and no
+    // values are in flight.
+    Idle,
+    // A packet went undelivered and stop_on_undelivered is set.
+    Undelivered,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeError {
+    Intcode(IntcodeError),
+    // The machine at this index has a route to a machine that does not exist,
+    // or packets of no values.
+    BadRoute(usize, Route),
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::Intcode(e) => write!(f, "{}", e),
+            RuntimeError::BadRoute(id, route) => write!(f, "machine {}: bad route {:?}", id, route),
+        }
+    }
+}
+
+impl Error for RuntimeError {}
+
+impl From<IntcodeError> for RuntimeError {
+    fn from(e: IntcodeError) -> Self {
+        RuntimeError::Intcode(e)
+    }
+}
+
+impl Route {
+    fn is_valid(&self, machines: usize) -> bool {
+        match self {
+            Route::Collect => true,
+            Route::To(to) => *to < machines,
+            Route::All(to) => to.iter().all(|t| *t < machines),
+            Route::Packets(size) => *size > 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub stop: Stop,
+    // Everything each machine sent, by machine.
+    pub outputs: Vec<Vec<i64>>,
+    pub undelivered: Vec<Vec<i64>>,
+}
+
+// Runs machines on their own threads, connected by channels.
+pub struct Runtime {
+    nodes: Vec<(Intcode, Route)>,
+    no_input: NoInput,
+    idle_after: usize,
+    stop_on_undelivered: bool,
+}
+
+struct State {
+    senders: Vec<Sender<i64>>,
+    // Values sent to each machine that it has not read yet.
+    queued: Vec<usize>,
+    waiting: Vec<bool>,
+    done: Vec<bool>,
+    outputs: Vec<Vec<i64>>,
+    undelivered: Vec<Vec<i64>>,
+    error: Option<IntcodeError>,
+    stop: Option<Stop>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+    // Set with stop, but checked by every machine after every instruction.
+    stopped: AtomicBool,
+    // Reads in a row that found no input, by machine. Only the machine's own
+    // thread touches its count, so it needs no lock.
+    empty_reads: Vec<AtomicUsize>,
+    no_input: NoInput,
+    idle_after: usize,
+    stop_on_undelivered: bool,
+}
+
+impl State {
+    // Values for a machine that has finished are dropped.
+    fn deliver(&mut self, to: usize, values: &[i64]) {
+        for v in values {
+            if self.senders[to].send(*v).is_ok() {
+                self.queued[to] += 1;
+            }
+        }
+    }
+
+    fn check(&self, shared: &Shared) -> Option<Stop> {
+        if self.done.iter().all(|d| *d) {
+            return Some(Stop::Halted);
+        }
+        if shared.stop_on_undelivered && !self.undelivered.is_empty() {
+            return Some(Stop::Undelivered);
+        }
+        let stuck =
+            (0..self.done.len()).all(|i| self.done[i] || self.waiting[i] && self.queued[i] == 0);
+        if stuck {
+            return Some(match shared.no_input {
+                NoInput::Block => Stop::Deadlock,
+                NoInput::Value(_) => Stop::Idle,
+            });
+        }
+        None
+    }
+}
+
+struct NodeInput<'a> {
+    id: usize,
+    pending: VecDeque<i64>,
+    rx: Receiver<i64>,
+    shared: &'a Shared,
+}
+
+impl NodeInput<'_> {
+    fn received(&self, v: i64) -> Option<i64> {
+        let mut state = self.shared.state.lock().unwrap();
+        state.queued[self.id] -= 1;
+        state.waiting[self.id] = false;
+        self.shared.empty_reads[self.id].store(0, Ordering::Relaxed);
+        Some(v)
+    }
+}
+
+impl InputSource for NodeInput<'_> {
+    fn next_input(&mut self) -> Option<i64> {
+        if let Some(v) = self.pending.pop_front() {
+            return Some(v);
+        }
+        loop {
+            if let Ok(v) = self.rx.try_recv() {
+                return self.received(v);
+            }
+            if self.shared.stopped.load(Ordering::Relaxed) {
+                return None;
+            }
+            let reads = self.shared.empty_reads[self.id].fetch_add(1, Ordering::Relaxed) + 1;
+            let idle_after = match self.shared.no_input {
+                NoInput::Block => 1,
+                NoInput::Value(_) => self.shared.idle_after,
+            };
+            if reads == idle_after {
+                let mut state = self.shared.state.lock().unwrap();
+                state.waiting[self.id] = true;
+                self.shared.changed.notify_all();
+            }
+            match self.shared.no_input {
+                // Lets the other machines run rather than spinning.
+                NoInput::Value(v) => {
+                    thread::yield_now();
+                    return Some(v);
+                }
+                // Wakes up now and then to see whether the network stopped.
+                NoInput::Block => {
+                    if let Ok(v) = self.rx.recv_timeout(Duration::from_millis(1)) {
+                        return self.received(v);
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct NodeOutput<'a> {
+    id: usize,
+    route: Route,
+    packet: Vec<i64>,
+    shared: &'a Shared,
+}
+
+impl OutputSink for NodeOutput<'_> {
+    // Deliveries happen with the state locked, so packets from different
+    // machines never interleave and queued never misses a value.
+    fn send_output(&mut self, value: i64) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.outputs[self.id].push(value);
+        state.waiting[self.id] = false;
+        self.shared.empty_reads[self.id].store(0, Ordering::Relaxed);
+        match &self.route {
+            Route::Collect => {}
+            Route::To(to) => state.deliver(*to, &[value]),
+            Route::All(to) => {
+                for t in to {
+                    state.deliver(*t, &[value]);
+                }
+            }
+            Route::Packets(size) => {
+                self.packet.push(value);
+                if self.packet.len() == *size {
+                    let to = self.packet[0];
+                    if to >= 0 && (to as usize) < state.senders.len() {
+                        state.deliver(to as usize, &self.packet[1..]);
+                    } else {
+                        state.undelivered.push(self.packet.clone());
+                        self.shared.changed.notify_all();
+                    }
+                    self.packet.clear();
+                }
+            }
+        }
+    }
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Runtime {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            no_input: NoInput::Block,
+            idle_after: 2,
+            stop_on_undelivered: false,
+        }
+    }
+
+    // Adds a machine and returns its index. Values already queued as its input
+    // are read before anything from the network.
+    pub fn add(&mut self, comp: Intcode, route: Route) -> usize {
+        self.nodes.push((comp, route));
+        self.nodes.len() - 1
+    }
+
+    pub fn set_no_input(&mut self, no_input: NoInput) {
+        self.no_input = no_input;
+    }
+
+    // How many no-input values in a row a machine reads before it counts as
+    // idle. Two by default, so a machine that just read its last value gets a
+    // chance to act on it.
+    pub fn set_idle_after(&mut self, reads: usize) {
+        self.idle_after = reads.max(1);
+    }
+
+    pub fn set_stop_on_undelivered(&mut self, stop: bool) {
+        self.stop_on_undelivered = stop;
+    }
+
+    // Runs until every machine halts, the network deadlocks or goes idle, or
+    // a packet goes undelivered if asked to stop then. The first error from
+    // any machine stops the network and is returned instead. Routes are
+    // checked before any machine starts, since they may name machines added
+    // after their own.
+    pub fn run(self) -> Result<Outcome, RuntimeError> {
+        let n = self.nodes.len();
+        for (id, (_, route)) in self.nodes.iter().enumerate() {
+            if !route.is_valid(n) {
+                return Err(RuntimeError::BadRoute(id, route.clone()));
+            }
+        }
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..n).map(|_| channel()).unzip();
+        let shared = Shared {
+            state: Mutex::new(State {
+                senders,
+                queued: vec![0; n],
+                waiting: vec![false; n],
+                done: vec![false; n],
+                outputs: vec![Vec::new(); n],
+                undelivered: Vec::new(),
+                error: None,
+                stop: None,
+            }),
+            changed: Condvar::new(),
+            stopped: AtomicBool::new(false),
+            empty_reads: (0..n).map(|_| AtomicUsize::new(0)).collect(),
+            no_input: self.no_input,
+            idle_after: self.idle_after,
+            stop_on_undelivered: self.stop_on_undelivered,
+        };
+
+        thread::scope(|s| {
+            for (id, ((comp, route), rx)) in self.nodes.into_iter().zip(receivers).enumerate() {
+                let shared = &shared;
+                let input = NodeInput {
+                    id,
+                    pending: comp.peek_inputs().into(),
+                    rx,
+                    shared,
+                };
+                let output = NodeOutput {
+                    id,
+                    route,
+                    packet: Vec::new(),
+                    shared,
+                };
+                let mut comp = comp.with_io(input, output);
+                s.spawn(move || {
+                    let result = loop {
+                        if shared.stopped.load(Ordering::Relaxed) {
+                            break Ok(());
+                        }
+                        match comp.step() {
+                            Ok(()) if comp.is_halted() || comp.awaiting_input() => break Ok(()),
+                            Ok(()) => {}
+                            Err(e) => break Err(e),
+                        }
+                    };
+                    let mut state = shared.state.lock().unwrap();
+                    state.done[id] = true;
+                    if let Err(e) = result {
+                        state.error.get_or_insert(e);
+                    }
+                    shared.changed.notify_all();
+                });
+            }
+
+            let mut state = shared.state.lock().unwrap();
+            while state.stop.is_none() {
+                state.stop = match state.error {
+                    Some(_) => Some(Stop::Halted),
+                    None => state.check(&shared),
+                };
+                if state.stop.is_none() {
+                    state = shared.changed.wait(state).unwrap();
+                }
+            }
+            shared.stopped.store(true, Ordering::Relaxed);
+        });
+
+        let state = shared.state.into_inner().unwrap();
+        match state.error {
+            Some(e) => Err(e.into()),
+            None => Ok(Outcome {
+                stop: state.stop.unwrap(),
+                outputs: state.outputs,
+                undelivered: state.undelivered,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorKind;
+
+    #[test]
+    fn pipeline_deadlocks() {
+        // Adds one to each input and outputs it, forever.
+        let s = "3, 100, 1001, 100, 1, 100, 4, 100, 1105, 1, 0".to_string();
+        let mut first = Intcode::intcode_instance(s.clone());
+        first.extend_input([1, 10]);
+        let mut net = Runtime::new();
+        net.add(first, Route::To(1));
+        net.add(Intcode::intcode_instance(s), Route::Collect);
+        let outcome = net.run().unwrap();
+        assert_eq!(outcome.stop, Stop::Deadlock);
+        assert_eq!(outcome.outputs, vec![vec![2, 11], vec![3, 12]]);
+    }
+
+    #[test]
+    fn ring_halts() {
+        // Reads a value, outputs it doubled, then halts.
+        let s = "3, 9, 1002, 9, 2, 9, 4, 9, 99, 0".to_string();
+        let mut net = Runtime::new();
+        for i in 0..3 {
+            let mut comp = Intcode::intcode_instance(s.clone());
+            if i == 0 {
+                comp.push_input(1);
+            }
+            net.add(comp, Route::To((i + 1) % 3));
+        }
+        let outcome = net.run().unwrap();
+        assert_eq!(outcome.stop, Stop::Halted);
+        assert_eq!(outcome.outputs[2], vec![8]);
+    }
+
+    #[test]
+    fn star() {
+        // Adds one to each input and outputs it, forever.
+        let s = "3, 100, 1001, 100, 1, 100, 4, 100, 1105, 1, 0".to_string();
+        let mut hub = Intcode::intcode_instance(s.clone());
+        hub.push_input(0);
+        let mut net = Runtime::new();
+        net.add(hub, Route::All(vec![1, 2]));
+        net.add(Intcode::intcode_instance(s.clone()), Route::Collect);
+        net.add(Intcode::intcode_instance(s), Route::Collect);
+        let outcome = net.run().unwrap();
+        assert_eq!(outcome.outputs[1], vec![2]);
+        assert_eq!(outcome.outputs[2], vec![2]);
+    }
+
+    #[test]
+    fn packets_go_idle() {
+        // Sends one packet (to, x, y) from its input, then reads forever,
+        // echoing any x, y it gets as a packet to address 9.
+        let s = "3, 100, 3, 101, 3, 102, 4, 100, 4, 101, 4, 102, \
+                 3, 101, 1008, 101, -1, 103, 1005, 103, 12, \
+                 3, 102, 104, 9, 4, 101, 4, 102, 1105, 1, 12"
+            .to_string();
+        let mut net = Runtime::new();
+        net.set_no_input(NoInput::Value(-1));
+        for packet in [[1, 5, 6], [7, 0, 0]] {
+            let mut comp = Intcode::intcode_instance(s.clone());
+            comp.extend_input(packet);
+            net.add(comp, Route::Packets(3));
+        }
+        let outcome = net.run().unwrap();
+        assert_eq!(outcome.stop, Stop::Idle);
+        assert_eq!(outcome.undelivered, vec![vec![7, 0, 0], vec![9, 5, 6]]);
+    }
+
+    #[test]
+    fn stop_on_undelivered() {
+        let s = "104, 5, 104, 1, 104, 2, 1105, 1, 0".to_string();
+        let mut net = Runtime::new();
+        net.set_stop_on_undelivered(true);
+        net.add(Intcode::intcode_instance(s), Route::Packets(3));
+        let outcome = net.run().unwrap();
+        assert_eq!(outcome.stop, Stop::Undelivered);
+        assert_eq!(outcome.undelivered[0], vec![5, 1, 2]);
+    }
+
+    #[test]
+    fn errors_stop_the_network() {
+        let s = "3, 100, 1001, 100, 1, 100, 4, 100, 1105, 1, 0".to_string();
+        let mut net = Runtime::new();
+        net.add(Intcode::intcode_instance(s), Route::Collect);
+        net.add(Intcode::intcode_instance("42".to_string()), Route::Collect);
+        match net.run().unwrap_err() {
+            RuntimeError::Intcode(e) => assert_eq!(e.kind, ErrorKind::InvalidOpcode),
+            e => panic!("unexpected {}", e),
+        }
+    }
+
+    #[test]
+    fn bad_routes() {
+        let s = "3, 100, 1001, 100, 1, 100, 4, 100, 1105, 1, 0".to_string();
+        for route in [Route::To(2), Route::All(vec![0, 5]), Route::Packets(0)] {
+            let mut net = Runtime::new();
+            net.add(Intcode::intcode_instance(s.clone()), Route::Collect);
+            net.add(Intcode::intcode_instance(s.clone()), route.clone());
+            assert_eq!(net.run(), Err(RuntimeError::BadRoute(1, route)));
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+// Everything needed to resume a machine. Saved to disk as JSON; the version
+// field is bumped whenever the layout changes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub pc: usize,
+    pub rb: i64,
+    pub memory: Vec<i64>,
+    pub input: Vec<i64>,
+    pub output: Vec<i64>,
+    pub halted: bool,
+    pub awaiting_input: bool,
+    // Instructions executed so far, which the step limit counts against.
+    pub steps: u64,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    Format(serde_json::Error),
+    Version(u32),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "{}", e),
+            SnapshotError::Format(e) => write!(f, "bad snapshot: {}", e),
+            SnapshotError::Version(v) => write!(f, "unsupported snapshot version {}", v),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(e: serde_json::Error) -> Self {
+        SnapshotError::Format(e)
+    }
+}
+
+impl Snapshot {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, SnapshotError> {
+        let snapshot: Snapshot = serde_json::from_str(s)?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::Version(snapshot.version));
+        }
+        Ok(snapshot)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        fs::write(path, self.to_json())?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Intcode as Computer, RunState};
+    use std::{env, process};
+
+    #[test]
+    fn restore_in_memory() {
+        let s = "3, 100, 4, 100, 1001, 100, 1, 100, 3, 101, 1105, 1, 2".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        comp.push_input(10);
+        assert_eq!(comp.run_until_output().unwrap(), RunState::Output(10));
+        let snap = comp.snapshot();
+        comp.push_input(0);
+        assert_eq!(comp.run_until_output().unwrap(), RunState::Output(11));
+        comp.restore_snapshot(&snap);
+        comp.push_input(0);
+        assert_eq!(comp.run_until_output().unwrap(), RunState::Output(11));
+        assert_eq!(comp.snapshot().memory[100], 11);
+    }
+
+    #[test]
+    fn named_snapshots() {
+        let s = "3, 100, 4, 100, 1001, 100, 1, 100, 3, 101, 1105, 1, 2".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        comp.push_input(5);
+        comp.run_until_output().unwrap();
+        comp.save_named("five");
+        comp.push_input(0);
+        comp.run_until_output().unwrap();
+        assert!(comp.restore_named("five"));
+        assert!(!comp.restore_named("missing"));
+        comp.push_input(0);
+        assert_eq!(comp.run_until_output().unwrap(), RunState::Output(6));
+    }
+
+    #[test]
+    fn disk_round_trip() {
+        let s = "3, 100, 4, 100, 1001, 100, 1, 100, 3, 101, 1105, 1, 2".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        comp.extend_input([1, 2, 3]);
+        comp.run_until_output().unwrap();
+        let name = format!("intcode_snapshot_test_{}.json", process::id());
+        let path = env::temp_dir().join(name);
+        comp.snapshot().save(&path).unwrap();
+        let snap = Snapshot::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(snap, comp.snapshot());
+        assert_eq!(snap.input, vec![2, 3]);
+        let mut copy = Computer::from_snapshot(&snap);
+        assert_eq!(copy.run_until_output().unwrap(), RunState::Output(2));
+    }
+
+    #[test]
+    fn restores_step_count() {
+        let s = "3, 100, 4, 100, 1001, 100, 1, 100, 3, 101, 1105, 1, 2".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        comp.extend_input([1, 0, 0]);
+        comp.run_until_output().unwrap();
+        let snap = comp.snapshot();
+        assert_eq!(snap.steps, 2);
+        comp.run_until_output().unwrap();
+        comp.restore_snapshot(&snap);
+        assert_eq!(comp.steps(), 2);
+        comp.set_step_limit(Some(6));
+        assert_eq!(comp.run_until_output().unwrap(), RunState::Output(2));
+        let copy = Snapshot::from_json(&snap.to_json()).unwrap();
+        assert_eq!(Computer::from_snapshot(&copy).steps(), 2);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut snap = Computer::intcode_instance("99".to_string()).snapshot();
+        snap.version = 99;
+        let json = snap.to_json();
+        assert!(matches!(
+            Snapshot::from_json(&json),
+            Err(SnapshotError::Version(99))
+        ));
+    }
+}
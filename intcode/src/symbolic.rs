@@ -0,0 +1,370 @@
+use crate::instruction::{decode_op, Mode, Opcode};
+use crate::io::{InputSource, OutputSink};
+use crate::memory::MEMORY_LIMIT;
+use crate::{address, ErrorKind, Intcode, IntcodeError, RunState};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+
+// constant + the sum of coefficient * symbol over terms. Terms never hold a
+// zero coefficient, so an expression without symbols has no terms.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Affine {
+    pub constant: i64,
+    pub terms: BTreeMap<String, i64>,
+}
+
+impl From<i64> for Affine {
+    fn from(constant: i64) -> Self {
+        Self {
+            constant,
+            terms: BTreeMap::new(),
+        }
+    }
+}
+
+impl Affine {
+    pub fn symbol(name: &str) -> Self {
+        Self {
+            constant: 0,
+            terms: BTreeMap::from([(name.to_string(), 1)]),
+        }
+    }
+
+    pub fn as_constant(&self) -> Option<i64> {
+        self.terms.is_empty().then_some(self.constant)
+    }
+
+    pub fn coefficient(&self, name: &str) -> i64 {
+        self.terms.get(name).copied().unwrap_or_default()
+    }
+
+    pub fn add(&self, other: &Affine) -> Affine {
+        let mut sum = self.clone();
+        sum.constant += other.constant;
+        for (name, k) in &other.terms {
+            let c = sum.terms.entry(name.clone()).or_default();
+            *c += k;
+            if *c == 0 {
+                sum.terms.remove(name);
+            }
+        }
+        sum
+    }
+
+    pub fn scale(&self, k: i64) -> Affine {
+        if k == 0 {
+            return Affine::from(0);
+        }
+        Affine {
+            constant: self.constant * k,
+            terms: (self.terms.iter())
+                .map(|(name, c)| (name.clone(), c * k))
+                .collect(),
+        }
+    }
+
+    // None if a symbol has no value.
+    pub fn eval(&self, values: &[(&str, i64)]) -> Option<i64> {
+        let mut total = self.constant;
+        for (name, k) in &self.terms {
+            let (_, v) = values.iter().find(|(n, _)| n == name)?;
+            total += k * v;
+        }
+        Some(total)
+    }
+
+    // The value of name that makes the expression equal target, given values
+    // for the other symbols. None if there is no whole number solution.
+    pub fn solve(&self, name: &str, target: i64, values: &[(&str, i64)]) -> Option<i64> {
+        let k = self.coefficient(name);
+        if k == 0 {
+            return None;
+        }
+        let mut rest = self.clone();
+        rest.terms.remove(name);
+        let diff = target - rest.eval(values)?;
+        (diff % k == 0).then_some(diff / k)
+    }
+}
+
+impl fmt::Display for Affine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        let mut sign = |f: &mut fmt::Formatter<'_>, negative: bool| {
+            let s = match (first, negative) {
+                (true, true) => "-",
+                (true, false) => "",
+                (false, true) => " - ",
+                (false, false) => " + ",
+            };
+            first = false;
+            write!(f, "{}", s)
+        };
+        for (name, k) in &self.terms {
+            sign(f, *k < 0)?;
+            match k.abs() {
+                1 => write!(f, "{}", name)?,
+                k => write!(f, "{} * {}", k, name)?,
+            }
+        }
+        if self.constant != 0 || self.terms.is_empty() {
+            sign(f, self.constant < 0)?;
+            write!(f, "{}", self.constant.abs())?;
+        }
+        Ok(())
+    }
+}
+
+// A symbolic value replaced by its concrete one, because it was used as an
+// address, a jump condition or target, a comparison or one side of a product
+// of two symbolic values. The results only hold while expr equals value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assumption {
+    pub pc: usize,
+    pub expr: Affine,
+    pub value: i64,
+}
+
+// Runs a program with some memory cells or inputs standing for symbols.
+// Every symbol also has a concrete value, so the run follows the same path as
+// a concrete one would, while ADD and MUL carry affine expressions of the
+// symbols along.
+#[derive(Debug, Clone)]
+pub struct Symbolic {
+    memory: Vec<Affine>,
+    pc: usize,
+    rb: i64,
+    input: VecDeque<Affine>,
+    output: Vec<Affine>,
+    halted: bool,
+    bindings: HashMap<String, i64>,
+    assumptions: Vec<Assumption>,
+}
+
+impl Symbolic {
+    pub fn new<I: InputSource, O: OutputSink>(comp: &Intcode<I, O>) -> Self {
+        Self {
+            memory: comp.memory().into_iter().map(Affine::from).collect(),
+            pc: comp.pc(),
+            rb: comp.rb(),
+            input: VecDeque::new(),
+            output: Vec::new(),
+            halted: comp.is_halted(),
+            bindings: HashMap::new(),
+            assumptions: Vec::new(),
+        }
+    }
+
+    // Makes the cell at addr the symbol name, keeping what it holds as the
+    // concrete value.
+    pub fn set_symbol(&mut self, addr: usize, name: &str) {
+        let value = self.concrete(&self.read(addr));
+        self.bindings.insert(name.to_string(), value);
+        self.write(addr, Affine::symbol(name));
+    }
+
+    pub fn push_input(&mut self, value: i64) {
+        self.input.push_back(Affine::from(value));
+    }
+
+    // Queues an input standing for the symbol name, with the given concrete
+    // value.
+    pub fn push_symbol(&mut self, name: &str, value: i64) {
+        self.bindings.insert(name.to_string(), value);
+        self.input.push_back(Affine::symbol(name));
+    }
+
+    // Reads past the end of memory see zero.
+    pub fn read(&self, addr: usize) -> Affine {
+        self.memory.get(addr).cloned().unwrap_or_default()
+    }
+
+    pub fn write(&mut self, addr: usize, value: Affine) {
+        if addr >= self.memory.len() {
+            self.memory.resize(addr + 1, Affine::default());
+        }
+        self.memory[addr] = value;
+    }
+
+    pub fn outputs(&self) -> &[Affine] {
+        &self.output
+    }
+
+    pub fn assumptions(&self) -> &[Assumption] {
+        &self.assumptions
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    fn concrete(&self, value: &Affine) -> i64 {
+        let mut total = value.constant;
+        for (name, k) in &value.terms {
+            total += k * self.bindings.get(name).copied().unwrap_or_default();
+        }
+        total
+    }
+
+    // The concrete value, noting the assumption if value is symbolic.
+    fn assume(&mut self, value: &Affine) -> i64 {
+        let v = self.concrete(value);
+        if value.as_constant().is_none() {
+            self.assumptions.push(Assumption {
+                pc: self.pc,
+                expr: value.clone(),
+                value: v,
+            });
+        }
+        v
+    }
+
+    fn fetch(&mut self, pos: usize) -> Result<i64, ErrorKind> {
+        let v = self.memory.get(pos).cloned();
+        let v = v.ok_or(ErrorKind::ReadPastProgram(pos))?;
+        Ok(self.assume(&v))
+    }
+
+    fn arg(&mut self, n: usize, mode: Mode) -> Result<Affine, ErrorKind> {
+        let arg = self.fetch(self.pc + n)?;
+        match mode {
+            Mode::Position => Ok(self.read(address(arg)?)),
+            Mode::Immediate => Ok(Affine::from(arg)),
+            Mode::Relative => Ok(self.read(self.relative(arg)?)),
+        }
+    }
+
+    fn relative(&self, arg: i64) -> Result<usize, ErrorKind> {
+        address(self.rb.checked_add(arg).ok_or(ErrorKind::Overflow)?)
+    }
+
+    fn dest(&mut self, n: usize, mode: Mode) -> Result<usize, ErrorKind> {
+        let arg = self.fetch(self.pc + n)?;
+        let addr = match mode {
+            Mode::Position => address(arg)?,
+            Mode::Immediate => return Err(ErrorKind::ImmediateWrite),
+            Mode::Relative => self.relative(arg)?,
+        };
+        if addr >= MEMORY_LIMIT {
+            return Err(ErrorKind::AddressTooLarge(addr));
+        }
+        Ok(addr)
+    }
+
+    // Runs until the program halts or needs an input.
+    pub fn run(&mut self) -> Result<RunState, IntcodeError> {
+        while !self.halted {
+            let pc = self.pc;
+            match self.step() {
+                Ok(true) => {}
+                Ok(false) => return Ok(RunState::NeedsInput),
+                Err(kind) => {
+                    let instr = self.concrete(&self.read(pc));
+                    return Err(IntcodeError { pc, instr, kind });
+                }
+            }
+        }
+        Ok(RunState::Halted)
+    }
+
+    // False when an input is needed.
+    fn step(&mut self) -> Result<bool, ErrorKind> {
+        let (op, modes) = decode_op(self.fetch(self.pc)?)?;
+        match op {
+            Opcode::Add | Opcode::Mul | Opcode::Lt | Opcode::Eq => {
+                let a = self.arg(1, modes[0])?;
+                let b = self.arg(2, modes[1])?;
+                let dest = self.dest(3, modes[2])?;
+                let diff = a.add(&b.scale(-1));
+                let val = match op {
+                    Opcode::Add => a.add(&b),
+                    Opcode::Mul => match (a.as_constant(), b.as_constant()) {
+                        (Some(k), _) => b.scale(k),
+                        (_, Some(k)) => a.scale(k),
+                        _ => Affine::from(self.assume(&a) * self.assume(&b)),
+                    },
+                    Opcode::Lt => Affine::from((self.assume(&diff) < 0) as i64),
+                    _ => Affine::from((self.assume(&diff) == 0) as i64),
+                };
+                self.write(dest, val);
+                self.pc += 4;
+            }
+            Opcode::In => {
+                let dest = self.dest(1, modes[0])?;
+                let Some(val) = self.input.pop_front() else {
+                    return Ok(false);
+                };
+                self.write(dest, val);
+                self.pc += 2;
+            }
+            Opcode::Out => {
+                let val = self.arg(1, modes[0])?;
+                self.output.push(val);
+                self.pc += 2;
+            }
+            Opcode::Jt | Opcode::Jf => {
+                let cond = self.arg(1, modes[0])?;
+                let target = self.arg(2, modes[1])?;
+                let cond = self.assume(&cond) != 0;
+                if cond == (op == Opcode::Jt) {
+                    self.pc = address(self.assume(&target))?;
+                } else {
+                    self.pc += 3;
+                }
+            }
+            Opcode::Arb => {
+                let val = self.arg(1, modes[0])?;
+                let offset = self.assume(&val);
+                self.rb = self.rb.checked_add(offset).ok_or(ErrorKind::Overflow)?;
+                self.pc += 2;
+            }
+            Opcode::Hlt => self.halted = true,
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbolic(program: &str) -> Symbolic {
+        Symbolic::new(&Intcode::intcode_instance(program.to_string()))
+    }
+
+    #[test]
+    fn affine_through_add_and_mul() {
+        // MUL [9], #3, [9]; ADD [9], [10], [0]; HLT; .data 7, 5
+        let mut sym = symbolic("1002, 9, 3, 9, 1, 9, 10, 0, 99, 7, 5");
+        sym.set_symbol(9, "x");
+        sym.set_symbol(10, "y");
+        assert_eq!(sym.run().unwrap(), RunState::Halted);
+        let result = sym.read(0);
+        assert_eq!(result.to_string(), "3 * x + y");
+        assert_eq!(result.eval(&[("x", 7), ("y", 5)]), Some(26));
+        assert_eq!(result.solve("y", 100, &[("x", 30)]), Some(10));
+        assert_eq!(result.solve("x", 100, &[("y", 0)]), None);
+        assert!(sym.assumptions().is_empty());
+    }
+
+    #[test]
+    fn inputs_outputs_and_assumptions() {
+        // IN [13]; MUL [13], [13], [14]; OUT [13]; EQ [13], #4, [15]; HLT
+        let mut sym = symbolic("3, 13, 2, 13, 13, 14, 4, 13, 1008, 13, 4, 15, 99");
+        sym.push_symbol("n", 4);
+        assert_eq!(sym.run().unwrap(), RunState::Halted);
+        assert_eq!(sym.outputs(), [Affine::symbol("n")]);
+        assert_eq!(sym.read(14), Affine::from(16));
+        assert_eq!(sym.read(15), Affine::from(1));
+        let assumed: Vec<_> = sym.assumptions().iter().map(|a| a.pc).collect();
+        assert_eq!(assumed, vec![2, 2, 8]);
+        assert_eq!(sym.assumptions()[2].expr.to_string(), "n - 4");
+    }
+
+    #[test]
+    fn huge_destination() {
+        let mut sym = symbolic("1101, 1, 1, 1000000000000000, 99");
+        let err = sym.run().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::AddressTooLarge(1000000000000000));
+    }
+}
@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// One executed instruction. Operands are the values read after applying their
+// modes. Instructions that write memory set dest and value; ARB sets value to
+// the new relative base. rb is the relative base the instruction ran with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceRecord {
+    pub pc: usize,
+    pub opcode: String,
+    pub modes: Vec<i64>,
+    pub operands: Vec<i64>,
+    pub dest: Option<usize>,
+    pub value: Option<i64>,
+    pub rb: i64,
+}
+
+// Where a machine writes its trace, one JSON record per line. Clones share the
+// writer, so a forked machine keeps tracing to the same place. Tracing is best
+// effort: write errors are dropped rather than stopping the machine.
+#[derive(Clone)]
+pub struct Trace {
+    writer: Arc<Mutex<dyn Write + Send>>,
+}
+
+impl Trace {
+    pub fn new<W: Write + Send + 'static>(writer: W) -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(writer)),
+        }
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+
+    pub fn file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+
+    pub fn record(&self, record: &TraceRecord) {
+        let mut writer = self.writer.lock().unwrap();
+        if serde_json::to_writer(&mut *writer, record).is_ok() {
+            let _ = writeln!(writer);
+        }
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        self.writer.lock().unwrap().flush()
+    }
+}
+
+// An in-memory trace, for tests and for post-processing a run in place.
+#[derive(Debug, Clone, Default)]
+pub struct TraceBuffer {
+    buf: Arc<Mutex<Vec<u8>>>,
+}
+
+impl TraceBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.buf.lock().unwrap()).into_owned()
+    }
+
+    pub fn records(&self) -> Vec<TraceRecord> {
+        self.contents()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.buf.lock().unwrap().clear();
+    }
+}
+
+impl Write for TraceBuffer {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.lock().unwrap().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Intcode as Computer;
+
+    fn traced(program: &str) -> (Computer, TraceBuffer) {
+        let mut comp = Computer::intcode_instance(program.to_string());
+        let buf = TraceBuffer::new();
+        comp.set_trace(Trace::new(buf.clone()));
+        (comp, buf)
+    }
+
+    #[test]
+    fn one_record_per_instruction() {
+        let (mut comp, buf) = traced("3, 9, 1002, 9, 3, 10, 4, 10, 99, 0, 0");
+        comp.set_input(5);
+        comp.run().unwrap();
+        let records = buf.records();
+        assert_eq!(records.len(), 4);
+        assert_eq!(
+            records[1],
+            TraceRecord {
+                pc: 2,
+                opcode: "MUL".to_string(),
+                modes: vec![0, 1, 0],
+                operands: vec![5, 3],
+                dest: Some(10),
+                value: Some(15),
+                rb: 0,
+            }
+        );
+        assert_eq!(records[0].dest, Some(9));
+        assert_eq!(records[0].value, Some(5));
+        assert_eq!(records[2].operands, vec![15]);
+        assert_eq!(records[3].opcode, "HLT");
+    }
+
+    #[test]
+    fn relative_base_and_jumps() {
+        let (mut comp, buf) = traced("109, 4, 1205, -2, 7, 99, 99, 99");
+        comp.run().unwrap();
+        let records = buf.records();
+        assert_eq!(records[0].value, Some(4));
+        assert_eq!(records[1].rb, 4);
+        assert_eq!(records[1].modes, vec![2, 1]);
+        assert_eq!(records[1].operands, vec![1205, 7]);
+        assert_eq!(records[2].pc, 7);
+    }
+
+    #[test]
+    fn json_lines() {
+        let (mut comp, buf) = traced("104, -1, 99");
+        comp.run().unwrap();
+        assert_eq!(
+            buf.contents(),
+            "{\"pc\":0,\"opcode\":\"OUT\",\"modes\":[1],\"operands\":[-1],\
+             \"dest\":null,\"value\":null,\"rb\":0}\n\
+             {\"pc\":2,\"opcode\":\"HLT\",\"modes\":[],\"operands\":[],\
+             \"dest\":null,\"value\":null,\"rb\":0}\n"
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    fn covers(self, other: Access) -> bool {
+        self == Access::ReadWrite || self == other
+    }
+}
+
+// A watched address was read or written, with its value before and after; the
+// two are the same for a read. pc is the instruction that made the access, or
+// None for Intcode::read and Intcode::write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchEvent {
+    pub pc: Option<usize>,
+    pub addr: usize,
+    pub access: Access,
+    pub old: i64,
+    pub new: i64,
+}
+
+// A value taken by the IN or sent by the OUT at pc.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoEvent {
+    pub pc: usize,
+    pub value: i64,
+}
+
+// What a watchpoint or hook wants the machine to do next. Pause stops run
+// with RunState::Paused once the instruction has finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Continue,
+    Pause,
+}
+
+// Clones share the callback, so a forked machine reports to the same place.
+type Callback<E> = Arc<Mutex<dyn FnMut(&E) -> Action + Send>>;
+
+fn call<E>(callback: &Callback<E>, event: &E) -> bool {
+    (callback.lock().unwrap())(event) == Action::Pause
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct Watchpoints {
+    watches: HashMap<usize, Vec<(usize, Access, Callback<WatchEvent>)>>,
+    next_id: usize,
+    on_input: Option<Callback<IoEvent>>,
+    on_output: Option<Callback<IoEvent>>,
+    pc: usize,
+    pub(crate) paused: bool,
+}
+
+impl Watchpoints {
+    pub(crate) fn add<F>(&mut self, addr: usize, access: Access, f: F) -> usize
+    where
+        F: FnMut(&WatchEvent) -> Action + Send + 'static,
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+        let callback: Callback<WatchEvent> = Arc::new(Mutex::new(f));
+        self.watches
+            .entry(addr)
+            .or_default()
+            .push((id, access, callback));
+        id
+    }
+
+    pub(crate) fn remove(&mut self, id: usize) -> bool {
+        for watches in self.watches.values_mut() {
+            if let Some(i) = watches.iter().position(|w| w.0 == id) {
+                watches.remove(i);
+                return true;
+            }
+        }
+        false
+    }
+
+    pub(crate) fn set_input_hook<F>(&mut self, f: F)
+    where
+        F: FnMut(&IoEvent) -> Action + Send + 'static,
+    {
+        self.on_input = Some(Arc::new(Mutex::new(f)));
+    }
+
+    pub(crate) fn set_output_hook<F>(&mut self, f: F)
+    where
+        F: FnMut(&IoEvent) -> Action + Send + 'static,
+    {
+        self.on_output = Some(Arc::new(Mutex::new(f)));
+    }
+
+    // Called before each instruction.
+    pub(crate) fn start(&mut self, pc: usize) {
+        self.pc = pc;
+        self.paused = false;
+    }
+
+    // Runs the callbacks watching the event's address and says whether any
+    // asked to pause.
+    pub(crate) fn notify(&self, event: &WatchEvent) -> bool {
+        let mut pause = false;
+        if let Some(watches) = self.watches.get(&event.addr) {
+            for (_, access, callback) in watches {
+                if access.covers(event.access) {
+                    pause |= call(callback, event);
+                }
+            }
+        }
+        pause
+    }
+
+    // An access made by the current instruction. Cold for the same reason as
+    // the profile counters.
+    #[cold]
+    pub(crate) fn access(&mut self, addr: usize, access: Access, old: i64, new: i64) {
+        if self.watches.contains_key(&addr) {
+            let event = WatchEvent {
+                pc: Some(self.pc),
+                addr,
+                access,
+                old,
+                new,
+            };
+            self.paused |= self.notify(&event);
+        }
+    }
+
+    #[cold]
+    pub(crate) fn input(&mut self, value: i64) {
+        if let Some(hook) = &self.on_input {
+            let event = IoEvent { pc: self.pc, value };
+            self.paused |= call(hook, &event);
+        }
+    }
+
+    #[cold]
+    pub(crate) fn output(&mut self, value: i64) {
+        if let Some(hook) = &self.on_output {
+            let event = IoEvent { pc: self.pc, value };
+            self.paused |= call(hook, &event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Intcode as Computer, RunState};
+
+    fn recorder<E: Copy + Send + 'static>(
+        pause: bool,
+    ) -> (Arc<Mutex<Vec<E>>>, impl FnMut(&E) -> Action + Send) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let seen = events.clone();
+        let f = move |e: &E| {
+            seen.lock().unwrap().push(*e);
+            if pause {
+                Action::Pause
+            } else {
+                Action::Continue
+            }
+        };
+        (events, f)
+    }
+
+    #[test]
+    fn writes() {
+        // OUT [10]; ADD [10], #-1, [10]; JT [10], #0; HLT; .data 3
+        let s = "4, 10, 1001, 10, -1, 10, 1005, 10, 0, 99, 3".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        let (events, f) = recorder(false);
+        comp.watch(10, Access::Write, f);
+        assert_eq!(comp.run().unwrap(), RunState::Halted);
+        let writes: Vec<_> = (events.lock().unwrap().iter())
+            .map(|e: &WatchEvent| (e.pc, e.old, e.new))
+            .collect();
+        assert_eq!(
+            writes,
+            vec![(Some(2), 3, 2), (Some(2), 2, 1), (Some(2), 1, 0)]
+        );
+    }
+
+    #[test]
+    fn reads_and_pauses() {
+        let s = "4, 10, 1001, 10, -1, 10, 1005, 10, 0, 99, 3".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        let (events, f) = recorder(true);
+        comp.watch(10, Access::Read, f);
+        assert_eq!(comp.run().unwrap(), RunState::Paused);
+        assert_eq!(comp.pc(), 2);
+        assert_eq!(comp.get_outputs(), vec![3]);
+        assert_eq!(comp.run().unwrap(), RunState::Paused);
+        assert_eq!(comp.pc(), 6);
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].access, Access::Read);
+        assert_eq!((events[1].old, events[1].new), (3, 3));
+    }
+
+    #[test]
+    fn direct_access_and_unwatch() {
+        let s = "4, 10, 1001, 10, -1, 10, 1005, 10, 0, 99, 3".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        let (events, f) = recorder(true);
+        let id = comp.watch(10, Access::ReadWrite, f);
+        comp.write(7, 10).unwrap();
+        assert_eq!(comp.read(10), 7);
+        assert!(!comp.paused());
+        assert_eq!(events.lock().unwrap()[0].pc, None);
+        assert_eq!(events.lock().unwrap().len(), 2);
+        assert!(comp.unwatch(id));
+        assert!(!comp.unwatch(id));
+        assert_eq!(comp.run().unwrap(), RunState::Halted);
+        assert_eq!(events.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn io_hooks() {
+        // IN [9]; OUT [9]; HLT
+        let s = "3, 9, 4, 9, 99, 0, 0, 0, 0, 0".to_string();
+        let mut comp = Computer::intcode_instance(s);
+        let (inputs, f) = recorder(false);
+        comp.on_input(f);
+        let (outputs, f) = recorder(true);
+        comp.on_output(f);
+        comp.push_input(42);
+        assert_eq!(comp.run().unwrap(), RunState::Paused);
+        assert_eq!(comp.run().unwrap(), RunState::Halted);
+        assert_eq!(*inputs.lock().unwrap(), vec![IoEvent { pc: 0, value: 42 }]);
+        assert_eq!(*outputs.lock().unwrap(), vec![IoEvent { pc: 2, value: 42 }]);
+    }
+}